@@ -0,0 +1,130 @@
+// Times what the list does between keypresses on inputs of one to ten
+// million lines, to check that moving around stays instant however much
+// was read:
+//
+//   cargo bench --bench navigation [lines...]
+
+// the modules are shared with the binary, which uses more of them
+#![allow(dead_code)]
+
+extern crate libc;
+extern crate unicode_segmentation;
+extern crate unicode_width;
+
+#[path = "../src/event.rs"]
+mod event;
+#[path = "../src/field.rs"]
+mod field;
+#[path = "../src/fuzzy.rs"]
+mod fuzzy;
+#[path = "../src/lines.rs"]
+mod lines;
+#[path = "../src/list.rs"]
+mod list;
+#[path = "../src/preview.rs"]
+mod preview;
+#[path = "../src/search.rs"]
+mod search;
+#[path = "../src/template.rs"]
+mod template;
+
+use list::ViList;
+
+use std::env;
+use std::time::{Duration, Instant};
+
+const STEPS: u32 = 1000;
+const BATCH: usize = 10_000;
+
+fn time<F: FnMut()>(mut f: F) -> Duration {
+    let start = Instant::now();
+    f();
+    start.elapsed()
+}
+
+// the work done for a single keypress, divided over STEPS of them
+fn per_key<F: FnMut(&mut ViList)>(list: &mut ViList, mut f: F) -> Duration {
+    time(|| {
+        for _ in 0..STEPS {
+            f(list);
+            list.frame(None);
+        }
+    }) / STEPS
+}
+
+fn bench(n: usize) {
+    let mut list = ViList::new(true);
+    list.height = 40;
+    list.width = 120;
+
+    let read = time(|| {
+        for start in (0..n).step_by(BATCH) {
+            let lines = (start..n.min(start + BATCH))
+                .map(|i| format!("src/module{}/component_{}/file{}.rs", i % 97, i % 1013, i))
+                .map(String::into_bytes)
+                .collect();
+
+            list.append(lines);
+        }
+    });
+
+    println!("{} lines", n);
+    println!("  read               {:?}", read);
+    println!(
+        "  down               {:?}",
+        per_key(&mut list, |l| l.down())
+    );
+    println!(
+        "  page down          {:?}",
+        per_key(&mut list, |l| l.page_down())
+    );
+    println!(
+        "  up from the top    {:?}",
+        per_key(&mut list, |l| {
+            l.selected = 0;
+            l.up()
+        })
+    );
+    println!(
+        "  mark and down      {:?}",
+        per_key(&mut list, |l| {
+            l.toggle_mark();
+            l.down()
+        })
+    );
+
+    // what is marked above stays marked, so this goes through every line
+    println!(
+        "  selections         {:?}",
+        per_key(&mut list, |l| {
+            l.selections();
+        })
+    );
+
+    let search = time(|| {
+        for c in "mod7file".chars() {
+            list.push_query(c);
+            list.search(|| false);
+        }
+    });
+
+    println!("  search, 8 keys     {:?}", search);
+    println!(
+        "  down, filtered     {:?}",
+        per_key(&mut list, |l| l.down())
+    );
+}
+
+fn main() {
+    let sizes: Vec<usize> = env::args().skip(1).filter_map(|a| a.parse().ok()).collect();
+
+    let sizes = if sizes.is_empty() {
+        vec![1_000_000, 10_000_000]
+    } else {
+        sizes
+    };
+
+    for n in sizes {
+        bench(n);
+    }
+}
@@ -0,0 +1,559 @@
+use key::{Code, Key, ALT, CTRL, SHIFT};
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum Action {
+    Up,
+    Down,
+    First,
+    Last,
+    Middle,
+    PageUp,
+    PageDown,
+    Accept,
+    Abort,
+    ToggleMark,
+    MarkAll,
+    UnmarkAll,
+    InvertMarks,
+    Search,
+    EndSearch,
+    BackwardDeleteChar,
+    ClearQuery,
+    Ignore,
+    Suspend,
+    Execute(String),
+    Reload(String),
+    ChangePrompt(String),
+}
+
+const ACTIONS: [(&str, Action); 19] = [
+    ("up", Action::Up),
+    ("down", Action::Down),
+    ("first", Action::First),
+    ("last", Action::Last),
+    ("middle", Action::Middle),
+    ("page-up", Action::PageUp),
+    ("page-down", Action::PageDown),
+    ("accept", Action::Accept),
+    ("abort", Action::Abort),
+    ("toggle-mark", Action::ToggleMark),
+    ("mark-all", Action::MarkAll),
+    ("unmark-all", Action::UnmarkAll),
+    ("invert-marks", Action::InvertMarks),
+    ("search", Action::Search),
+    ("end-search", Action::EndSearch),
+    ("backward-delete-char", Action::BackwardDeleteChar),
+    ("clear-query", Action::ClearQuery),
+    ("ignore", Action::Ignore),
+    ("suspend", Action::Suspend),
+];
+
+const NAMED_KEYS: [(&str, Code); 22] = [
+    ("enter", Code::Enter),
+    ("return", Code::Enter),
+    ("tab", Code::Tab),
+    ("bspace", Code::Backspace),
+    ("backspace", Code::Backspace),
+    ("esc", Code::Esc),
+    ("space", Code::Char(' ')),
+    ("colon", Code::Char(':')),
+    ("comma", Code::Char(',')),
+    ("up", Code::Up),
+    ("down", Code::Down),
+    ("left", Code::Left),
+    ("right", Code::Right),
+    ("home", Code::Home),
+    ("end", Code::End),
+    ("pgup", Code::PageUp),
+    ("page-up", Code::PageUp),
+    ("pgdn", Code::PageDown),
+    ("page-down", Code::PageDown),
+    ("ins", Code::Insert),
+    ("del", Code::Delete),
+    ("delete", Code::Delete),
+];
+
+const NORMAL: [(&str, Action); 31] = [
+    ("q", Action::Abort),
+    ("esc", Action::Abort),
+    ("ctrl-c", Action::Abort),
+    ("ctrl-g", Action::Abort),
+    ("ctrl-z", Action::Suspend),
+    ("k", Action::Up),
+    ("h", Action::Up),
+    ("up", Action::Up),
+    ("left", Action::Up),
+    ("j", Action::Down),
+    ("l", Action::Down),
+    ("down", Action::Down),
+    ("right", Action::Down),
+    ("g", Action::First),
+    ("home", Action::First),
+    ("G", Action::Last),
+    ("end", Action::Last),
+    ("z", Action::Middle),
+    ("pgup", Action::PageUp),
+    ("ctrl-b", Action::PageUp),
+    ("pgdn", Action::PageDown),
+    ("ctrl-f", Action::PageDown),
+    ("/", Action::Search),
+    ("enter", Action::Accept),
+    ("tab", Action::ToggleMark),
+    ("space", Action::ToggleMark),
+    ("a", Action::MarkAll),
+    ("u", Action::UnmarkAll),
+    ("i", Action::InvertMarks),
+    ("ctrl-n", Action::Down),
+    ("ctrl-p", Action::Up),
+];
+
+const SEARCH: [(&str, Action); 14] = [
+    ("enter", Action::Accept),
+    ("tab", Action::ToggleMark),
+    ("esc", Action::EndSearch),
+    ("bspace", Action::BackwardDeleteChar),
+    ("down", Action::Down),
+    ("ctrl-n", Action::Down),
+    ("up", Action::Up),
+    ("ctrl-p", Action::Up),
+    ("pgdn", Action::PageDown),
+    ("pgup", Action::PageUp),
+    ("ctrl-u", Action::ClearQuery),
+    ("ctrl-c", Action::Abort),
+    ("ctrl-g", Action::Abort),
+    ("ctrl-z", Action::Suspend),
+];
+
+type Chain = Vec<Action>;
+
+pub enum Lookup {
+    Actions(Vec<Action>),
+    Pending,
+    Insert(char),
+    None,
+}
+
+// Keys bound in normal mode may be sequences like `gg`.  While searching,
+// unbound printable characters are inserted into the query instead, so
+// bindings for plain characters only apply in normal mode; everything else,
+// like `ctrl-x` or `enter`, is bound in both modes.
+pub struct Bindings {
+    normal: Vec<(Vec<Key>, Chain)>,
+    search: Vec<(Key, Chain)>,
+    pending: Vec<Key>,
+}
+
+fn parse_key(spec: &str) -> Result<Key, String> {
+    let mut mods = 0;
+    let mut rest = spec;
+
+    loop {
+        if let Some(r) = rest.strip_prefix("ctrl-") {
+            mods |= CTRL;
+            rest = r;
+        } else if let Some(r) = rest.strip_prefix("alt-") {
+            mods |= ALT;
+            rest = r;
+        } else if let Some(r) = rest.strip_prefix("shift-") {
+            mods |= SHIFT;
+            rest = r;
+        } else {
+            break;
+        }
+    }
+
+    if rest == "btab" {
+        return Ok(Key::new(Code::Tab, mods | SHIFT));
+    }
+
+    if let Some(&(_, code)) = NAMED_KEYS.iter().find(|&&(name, _)| name == rest) {
+        return Ok(Key::new(code, mods));
+    }
+
+    if let Some(n) = rest.strip_prefix('f').and_then(|n| n.parse::<u8>().ok()) {
+        if (1..=12).contains(&n) {
+            return Ok(Key::new(Code::F(n), mods));
+        }
+    }
+
+    let mut chars = rest.chars();
+
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => {
+            // ctrl-A and ctrl-a are the same byte
+            let c = if mods & CTRL != 0 {
+                c.to_ascii_lowercase()
+            } else {
+                c
+            };
+
+            Ok(Key::new(Code::Char(c), mods))
+        }
+        _ => Err(format!("unknown key '{}'", spec)),
+    }
+}
+
+// a run of plain characters like `gg` is a sequence, anything else is a
+// single key
+fn parse_keys(spec: &str) -> Result<Vec<Key>, String> {
+    if let Ok(key) = parse_key(spec) {
+        return Ok(vec![key]);
+    }
+
+    let modified = ["ctrl-", "alt-", "shift-"]
+        .iter()
+        .any(|m| spec.starts_with(m));
+
+    if !modified && spec.chars().all(|c| !c.is_whitespace() && !c.is_control()) {
+        return Ok(spec.chars().map(|c| Key::new(Code::Char(c), 0)).collect());
+    }
+
+    Err(format!("unknown key '{}'", spec))
+}
+
+// splits on sep, except inside parentheses
+fn split_outside_parens(s: &str, sep: char) -> Vec<&str> {
+    let mut parts = vec![];
+    let mut depth = 0;
+    let mut start = 0;
+
+    for (i, c) in s.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' if depth > 0 => depth -= 1,
+            c if c == sep && depth == 0 => {
+                parts.push(&s[start..i]);
+                start = i + c.len_utf8();
+            }
+            _ => {}
+        }
+    }
+
+    parts.push(&s[start..]);
+    parts
+}
+
+fn parse_action(spec: &str) -> Result<Action, String> {
+    if let Some(open) = spec.find('(') {
+        if !spec.ends_with(')') {
+            return Err(format!("unterminated argument in '{}'", spec));
+        }
+
+        let arg = spec[open + 1..spec.len() - 1].to_string();
+
+        return match &spec[..open] {
+            "execute" => Ok(Action::Execute(arg)),
+            "reload" => Ok(Action::Reload(arg)),
+            "change-prompt" => Ok(Action::ChangePrompt(arg)),
+            name => Err(format!("unknown action '{}'", name)),
+        };
+    }
+
+    ACTIONS
+        .iter()
+        .find(|&&(n, _)| n == spec)
+        .map(|(_, a)| a.clone())
+        .ok_or_else(|| format!("unknown action '{}'", spec))
+}
+
+// actions are chained with +, like reload(git branch)+first
+fn parse_chain(spec: &str) -> Result<Chain, String> {
+    split_outside_parens(spec, '+')
+        .into_iter()
+        .map(|a| parse_action(a.trim()))
+        .collect()
+}
+
+fn is_plain(keys: &[Key]) -> bool {
+    keys.len() > 1
+        || matches!(
+            keys[0],
+            Key {
+                code: Code::Char(_),
+                mods: 0,
+            }
+        )
+}
+
+impl Bindings {
+    pub fn new() -> Bindings {
+        let mut bindings = Bindings {
+            normal: vec![],
+            search: vec![],
+            pending: vec![],
+        };
+
+        for (key, action) in NORMAL.iter() {
+            let keys = parse_keys(key).unwrap();
+            bindings.normal.push((keys, vec![action.clone()]));
+        }
+
+        for (key, action) in SEARCH.iter() {
+            let key = parse_key(key).unwrap();
+            bindings.search.push((key, vec![action.clone()]));
+        }
+
+        bindings
+    }
+
+    fn add(&mut self, keys: Vec<Key>, chain: Chain) {
+        if !is_plain(&keys) {
+            self.search.retain(|&(k, _)| k != keys[0]);
+            self.search.push((keys[0], chain.clone()));
+        }
+
+        self.normal.retain(|(k, _)| *k != keys);
+        self.normal.push((keys, chain));
+    }
+
+    // parses bindings of the form key:action[+action...][,key:action...]
+    pub fn bind(&mut self, spec: &str) -> Result<(), String> {
+        for binding in split_outside_parens(spec, ',') {
+            if binding.is_empty() {
+                continue;
+            }
+
+            let colon = binding
+                .find(':')
+                .ok_or_else(|| format!("expected key:action, got '{}'", binding))?;
+
+            let keys = parse_keys(binding[..colon].trim())?;
+            let chain = parse_chain(binding[colon + 1..].trim())?;
+
+            self.add(keys, chain);
+        }
+
+        Ok(())
+    }
+
+    // reads the [bind] section of a config file, one `key = action` per
+    // line; a missing file is not an error
+    pub fn load(&mut self, path: &Path) -> Result<(), String> {
+        let config = match fs::read_to_string(path) {
+            Ok(config) => config,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(format!("{}: {}", path.display(), e)),
+        };
+
+        let mut section = String::new();
+
+        for (n, line) in config.lines().enumerate() {
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            if line.starts_with('[') && line.ends_with(']') {
+                section = line[1..line.len() - 1].trim().to_string();
+                continue;
+            }
+
+            if section != "bind" {
+                continue;
+            }
+
+            let err = |e: String| format!("{}:{}: {}", path.display(), n + 1, e);
+
+            let eq = line
+                .find('=')
+                .ok_or_else(|| err(format!("expected key = action, got '{}'", line)))?;
+
+            let keys = parse_keys(line[..eq].trim()).map_err(err)?;
+            let chain = parse_chain(line[eq + 1..].trim()).map_err(err)?;
+
+            self.add(keys, chain);
+        }
+
+        Ok(())
+    }
+
+    pub fn lookup(&mut self, key: Key, searching: bool) -> Lookup {
+        if searching {
+            self.pending.clear();
+
+            return match self.search.iter().find(|&&(k, _)| k == key) {
+                Some((_, chain)) => Lookup::Actions(chain.clone()),
+                None => match key {
+                    Key {
+                        code: Code::Char(c),
+                        mods: 0,
+                    } => Lookup::Insert(c),
+                    _ => Lookup::None,
+                },
+            };
+        }
+
+        self.pending.push(key);
+
+        let longer = self
+            .normal
+            .iter()
+            .any(|(k, _)| k.len() > self.pending.len() && k.starts_with(&self.pending));
+
+        if longer {
+            return Lookup::Pending;
+        }
+
+        if let Some(chain) = self.exact() {
+            self.pending.clear();
+            return Lookup::Actions(chain);
+        }
+
+        // the sequence went nowhere: run what the keys before this one were
+        // bound to on their own, then start over from this key
+        self.pending.pop();
+        let prefix = self.exact();
+        let had_prefix = !self.pending.is_empty();
+        self.pending.clear();
+
+        if !had_prefix {
+            return Lookup::None;
+        }
+
+        let mut actions = prefix.unwrap_or_default();
+
+        match self.lookup(key, false) {
+            Lookup::Actions(rest) => actions.extend(rest),
+            Lookup::Pending if actions.is_empty() => return Lookup::Pending,
+            _ => {}
+        }
+
+        if actions.is_empty() {
+            Lookup::None
+        } else {
+            Lookup::Actions(actions)
+        }
+    }
+
+    fn exact(&self) -> Option<Chain> {
+        self.normal
+            .iter()
+            .find(|(k, _)| *k == self.pending)
+            .map(|(_, chain)| chain.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_chain, parse_key, parse_keys, Action, Bindings, Lookup};
+    use key::{Code, Key, CTRL};
+
+    fn ch(c: char) -> Key {
+        Key::new(Code::Char(c), 0)
+    }
+
+    fn bindings(spec: &str) -> Bindings {
+        let mut bindings = Bindings::new();
+        bindings.bind(spec).unwrap();
+        bindings
+    }
+
+    // what each key in turn was looked up as, in normal mode
+    fn press(bindings: &mut Bindings, keys: &str) -> Vec<Option<Vec<Action>>> {
+        keys.chars()
+            .map(|c| match bindings.lookup(ch(c), false) {
+                Lookup::Actions(actions) => Some(actions),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn a_sequence_waits_for_its_last_key() {
+        let mut b = bindings("gg:last");
+
+        assert_eq!(press(&mut b, "gg"), [None, Some(vec![Action::Last])]);
+    }
+
+    #[test]
+    fn a_prefix_that_goes_nowhere_runs_its_own_binding() {
+        let mut b = bindings("gg:last");
+
+        assert_eq!(
+            press(&mut b, "Ggj"),
+            [
+                Some(vec![Action::Last]),
+                None,
+                Some(vec![Action::First, Action::Down]),
+            ]
+        );
+    }
+
+    #[test]
+    fn a_prefix_that_goes_nowhere_starts_over_from_the_next_key() {
+        let mut b = bindings("gg:last,jk:accept");
+
+        assert_eq!(
+            press(&mut b, "gjk"),
+            [None, Some(vec![Action::First]), Some(vec![Action::Accept])]
+        );
+    }
+
+    #[test]
+    fn an_unbound_prefix_can_lead_into_another_sequence() {
+        let mut b = bindings("xy:abort,jk:accept");
+
+        assert_eq!(
+            press(&mut b, "xjk"),
+            [None, None, Some(vec![Action::Accept])]
+        );
+    }
+
+    #[test]
+    fn ctrl_keys_ignore_case() {
+        assert_eq!(parse_key("ctrl-A"), parse_key("ctrl-a"));
+        assert_eq!(parse_key("ctrl-a"), Ok(Key::new(Code::Char('a'), CTRL)));
+        assert_ne!(parse_key("A"), parse_key("a"));
+    }
+
+    #[test]
+    fn named_keys_and_sequences() {
+        assert_eq!(parse_key("bspace"), Ok(Key::new(Code::Backspace, 0)));
+        assert_eq!(parse_key("colon"), Ok(ch(':')));
+        assert_eq!(parse_keys("gg"), Ok(vec![ch('g'), ch('g')]));
+        assert!(parse_keys("ctrl-xy").is_err());
+        assert!(parse_key("f13").is_err());
+    }
+
+    #[test]
+    fn chains_split_outside_parentheses() {
+        assert_eq!(
+            parse_chain("reload(a,b)+first"),
+            Ok(vec![Action::Reload("a,b".to_string()), Action::First])
+        );
+
+        let mut b = bindings("ctrl-r:reload(a+b, c)+first,q:accept");
+
+        match b.lookup(Key::new(Code::Char('r'), CTRL), false) {
+            Lookup::Actions(actions) => assert_eq!(
+                actions,
+                [Action::Reload("a+b, c".to_string()), Action::First]
+            ),
+            _ => panic!("ctrl-r is not bound"),
+        }
+
+        assert_eq!(press(&mut b, "q"), [Some(vec![Action::Accept])]);
+    }
+
+    #[test]
+    fn plain_chars_are_typed_while_searching() {
+        let mut b = bindings("x:accept,ctrl-x:abort");
+
+        match b.lookup(ch('x'), true) {
+            Lookup::Insert('x') => {}
+            _ => panic!("x was not inserted"),
+        }
+
+        match b.lookup(Key::new(Code::Char('x'), CTRL), true) {
+            Lookup::Actions(actions) => assert_eq!(actions, [Action::Abort]),
+            _ => panic!("ctrl-x is not bound while searching"),
+        }
+
+        assert_eq!(press(&mut b, "x"), [Some(vec![Action::Accept])]);
+    }
+}
@@ -0,0 +1,69 @@
+use std::fmt;
+use std::io;
+
+// exit statuses, also listed in the --help output
+pub const EXIT_NO_MATCH: i32 = 1;
+pub const EXIT_ERROR: i32 = 2;
+pub const EXIT_NO_INPUT: i32 = 3;
+pub const EXIT_EXEC: i32 = 126;
+pub const EXIT_NOT_FOUND: i32 = 127;
+pub const EXIT_SIGNAL: i32 = 128;
+pub const EXIT_ABORT: i32 = 130;
+
+pub enum Error {
+    Usage(String),
+    Tty(io::Error),
+    Terminal(io::Error),
+    Output(io::Error),
+    NoInput,
+    NoMatch,
+    Abort,
+    Terminated(i32),
+    NotFound(String),
+    Exec(String, io::Error),
+    Failed(i32),
+    Killed(i32),
+}
+
+impl Error {
+    pub fn code(&self) -> i32 {
+        match *self {
+            Error::Usage(_) | Error::Tty(_) | Error::Terminal(_) | Error::Output(_) => EXIT_ERROR,
+            Error::NoInput => EXIT_NO_INPUT,
+            Error::NoMatch => EXIT_NO_MATCH,
+            Error::Abort => EXIT_ABORT,
+            Error::NotFound(_) => EXIT_NOT_FOUND,
+            Error::Exec(..) => EXIT_EXEC,
+            Error::Failed(code) => code,
+            Error::Terminated(sig) | Error::Killed(sig) => EXIT_SIGNAL + sig,
+        }
+    }
+
+    // the user knows they aborted, and a failing command has had its own
+    // say on stderr already
+    pub fn is_silent(&self) -> bool {
+        matches!(
+            *self,
+            Error::Abort | Error::NoMatch | Error::Terminated(_) | Error::Failed(_)
+        )
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Usage(ref msg) => write!(f, "{}", msg),
+            Error::Tty(ref e) => write!(f, "cannot open /dev/tty: {}", e),
+            Error::Terminal(ref e) => write!(f, "cannot use /dev/tty as a terminal: {}", e),
+            Error::Output(ref e) => write!(f, "cannot write output: {}", e),
+            Error::NoInput => write!(f, "no input"),
+            Error::NoMatch => write!(f, "no match"),
+            Error::Abort => write!(f, "aborted"),
+            Error::Terminated(sig) => write!(f, "terminated by signal {}", sig),
+            Error::NotFound(ref cmd) => write!(f, "{}: command not found", cmd),
+            Error::Exec(ref cmd, ref e) => write!(f, "{}: {}", cmd, e),
+            Error::Failed(code) => write!(f, "command exited with {}", code),
+            Error::Killed(sig) => write!(f, "command killed by signal {}", sig),
+        }
+    }
+}
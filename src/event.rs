@@ -0,0 +1,197 @@
+use libc;
+
+use std::os::unix::io::RawFd;
+use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+
+pub enum Event {
+    Key,
+    Wake,
+    Resize,
+    Terminate(i32),
+}
+
+static SIGNAL_FD: AtomicI32 = AtomicI32::new(-1);
+static RESIZED: AtomicBool = AtomicBool::new(false);
+static TERMINATED: AtomicI32 = AtomicI32::new(0);
+
+fn poke() {
+    let fd = SIGNAL_FD.load(Ordering::SeqCst);
+
+    if fd >= 0 {
+        Waker { fd }.wake();
+    }
+}
+
+extern "C" fn on_sigwinch(_: libc::c_int) {
+    RESIZED.store(true, Ordering::SeqCst);
+    poke();
+}
+
+extern "C" fn on_terminate(sig: libc::c_int) {
+    TERMINATED.store(sig, Ordering::SeqCst);
+    poke();
+}
+
+fn handle(sig: libc::c_int, handler: extern "C" fn(libc::c_int)) {
+    unsafe {
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = handler as *const () as libc::sighandler_t;
+        action.sa_flags = libc::SA_RESTART;
+        libc::sigemptyset(&mut action.sa_mask);
+        libc::sigaction(sig, &action, std::ptr::null_mut());
+    }
+}
+
+// runs f with ctrl-c and ctrl-\ left to whatever has the terminal, the way
+// system(3) does while it waits for its command, so that interrupting a
+// command run from a binding does not take the selector down with it
+pub fn ignoring_interrupts<T, F: FnOnce() -> T>(f: F) -> T {
+    let sigs = [libc::SIGINT, libc::SIGQUIT];
+
+    let mut old: [libc::sigaction; 2] = unsafe { std::mem::zeroed() };
+
+    unsafe {
+        let mut ignore: libc::sigaction = std::mem::zeroed();
+        ignore.sa_sigaction = libc::SIG_IGN;
+        libc::sigemptyset(&mut ignore.sa_mask);
+
+        for (sig, old) in sigs.iter().zip(old.iter_mut()) {
+            libc::sigaction(*sig, &ignore, old);
+        }
+    }
+
+    let result = f();
+
+    unsafe {
+        for (sig, old) in sigs.iter().zip(old.iter()) {
+            libc::sigaction(*sig, old, std::ptr::null_mut());
+        }
+    }
+
+    result
+}
+
+// a self-pipe, so that other threads can interrupt the wait for a keypress
+pub struct Events {
+    tty: RawFd,
+    read: RawFd,
+    write: RawFd,
+}
+
+#[derive(Clone, Copy)]
+pub struct Waker {
+    fd: RawFd,
+}
+
+impl Waker {
+    pub fn wake(&self) {
+        unsafe {
+            libc::write(self.fd, [0u8].as_ptr() as *const libc::c_void, 1);
+        }
+    }
+}
+
+impl Events {
+    pub fn new(tty: RawFd) -> Events {
+        let mut fds = [0; 2];
+
+        unsafe {
+            libc::pipe(fds.as_mut_ptr());
+
+            for &fd in &fds {
+                libc::fcntl(fd, libc::F_SETFL, libc::O_NONBLOCK);
+                libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC);
+            }
+        }
+
+        Events {
+            tty,
+            read: fds[0],
+            write: fds[1],
+        }
+    }
+
+    // the handlers only set a flag and poke the pipe, which is all that is
+    // safe to do from a signal handler; being asked to terminate is turned
+    // into an event so the terminal can be restored on the way out
+    pub fn watch_signals(&self) {
+        SIGNAL_FD.store(self.write, Ordering::SeqCst);
+
+        handle(libc::SIGWINCH, on_sigwinch);
+
+        for &sig in &[libc::SIGINT, libc::SIGTERM, libc::SIGHUP] {
+            handle(sig, on_terminate);
+        }
+    }
+
+    pub fn waker(&self) -> Waker {
+        Waker { fd: self.write }
+    }
+
+    fn drain(&self) {
+        let mut buf = [0u8; 64];
+
+        loop {
+            let n =
+                unsafe { libc::read(self.read, buf.as_mut_ptr() as *mut libc::c_void, buf.len()) };
+
+            if n <= 0 {
+                break;
+            }
+        }
+    }
+
+    // whether there is anything other than a wakeup to handle, for work done
+    // on the ui thread to check between steps
+    pub fn pending(&self) -> bool {
+        if TERMINATED.load(Ordering::SeqCst) != 0 || RESIZED.load(Ordering::SeqCst) {
+            return true;
+        }
+
+        let mut fd = libc::pollfd {
+            fd: self.tty,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+
+        unsafe { libc::poll(&mut fd, 1, 0) > 0 }
+    }
+
+    // keypresses take priority over wakeups, so a flood of background
+    // updates can never starve the input
+    pub fn wait(&self) -> Event {
+        let mut fds = [
+            libc::pollfd {
+                fd: self.tty,
+                events: libc::POLLIN,
+                revents: 0,
+            },
+            libc::pollfd {
+                fd: self.read,
+                events: libc::POLLIN,
+                revents: 0,
+            },
+        ];
+
+        loop {
+            let n = unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, -1) };
+
+            if n > 0 {
+                break;
+            }
+        }
+
+        let sig = TERMINATED.swap(0, Ordering::SeqCst);
+
+        if sig != 0 {
+            Event::Terminate(sig)
+        } else if RESIZED.swap(false, Ordering::SeqCst) {
+            Event::Resize
+        } else if fds[0].revents != 0 {
+            Event::Key
+        } else {
+            self.drain();
+            Event::Wake
+        }
+    }
+}
@@ -0,0 +1,246 @@
+// Lines split into fields, for --with-nth, --nth and placeholders like {2..}.
+//
+// Without --delimiter fields are separated by runs of whitespace, and any at
+// the start or end of the line is ignored.  With it they are separated by each
+// occurrence of the delimiter, so empty fields are possible.  Either way a
+// field never includes its separators, while a range of fields includes the
+// separators between them.
+//
+// Fields are counted from 1, and from -1 backwards from the last.
+
+#[derive(Clone)]
+pub enum Delimiter {
+    Whitespace,
+    Exact(Vec<u8>),
+}
+
+#[derive(Clone, Copy)]
+pub struct Range {
+    from: Option<i64>,
+    to: Option<i64>,
+}
+
+fn parse_index(s: &str) -> Option<Option<i64>> {
+    if s.is_empty() {
+        return Some(None);
+    }
+
+    match s.parse::<i64>() {
+        Ok(0) | Err(_) => None,
+        Ok(n) => Some(Some(n)),
+    }
+}
+
+fn resolve(n: i64, len: usize) -> i64 {
+    if n < 0 {
+        len as i64 + n
+    } else {
+        n - 1
+    }
+}
+
+impl Range {
+    // one of 2, -1, 2.., ..2 or 2..3
+    pub fn parse(s: &str) -> Option<Range> {
+        if let Some(dots) = s.find("..") {
+            let from = parse_index(&s[..dots])?;
+            let to = parse_index(&s[dots + 2..])?;
+
+            return Some(Range { from, to });
+        }
+
+        let n = parse_index(s)??;
+
+        Some(Range {
+            from: Some(n),
+            to: Some(n),
+        })
+    }
+
+    // a comma separated list of ranges, as given to --nth
+    pub fn parse_list(s: &str) -> Result<Vec<Range>, String> {
+        s.split(',')
+            .map(|r| Range::parse(r.trim()).ok_or_else(|| format!("invalid field range '{}'", r)))
+            .collect()
+    }
+
+    // the indices of the first and last field covered, if any
+    fn bounds(&self, len: usize) -> Option<(usize, usize)> {
+        let from = self.from.map(|n| resolve(n, len)).unwrap_or(0).max(0);
+        let to = self
+            .to
+            .map(|n| resolve(n, len))
+            .unwrap_or(len as i64 - 1)
+            .min(len as i64 - 1);
+
+        if from > to {
+            None
+        } else {
+            Some((from as usize, to as usize))
+        }
+    }
+}
+
+impl Delimiter {
+    pub fn parse(s: &str) -> Delimiter {
+        match s {
+            "" => Delimiter::Whitespace,
+            "\\t" => Delimiter::Exact(vec![b'\t']),
+            s => Delimiter::Exact(s.as_bytes().to_vec()),
+        }
+    }
+
+    // byte offsets of the start and end of every field
+    fn fields(&self, line: &[u8]) -> Vec<(usize, usize)> {
+        let mut fields = vec![];
+
+        match *self {
+            Delimiter::Whitespace => {
+                let mut start = None;
+
+                for (i, c) in line.iter().enumerate() {
+                    match (c.is_ascii_whitespace(), start) {
+                        (true, Some(s)) => {
+                            fields.push((s, i));
+                            start = None;
+                        }
+                        (false, None) => start = Some(i),
+                        _ => {}
+                    }
+                }
+
+                if let Some(s) = start {
+                    fields.push((s, line.len()));
+                }
+            }
+            Delimiter::Exact(ref delim) => {
+                let mut start = 0;
+                let mut i = 0;
+
+                while i + delim.len() <= line.len() {
+                    if line[i..].starts_with(delim) {
+                        fields.push((start, i));
+                        i += delim.len();
+                        start = i;
+                    } else {
+                        i += 1;
+                    }
+                }
+
+                fields.push((start, line.len()));
+            }
+        }
+
+        fields
+    }
+
+    // byte offsets of the text covered by each of the ranges
+    pub fn spans(&self, line: &[u8], ranges: &[Range]) -> Vec<(usize, usize)> {
+        let fields = self.fields(line);
+
+        ranges
+            .iter()
+            .filter_map(|r| r.bounds(fields.len()))
+            .map(|(from, to)| (fields[from].0, fields[to].1))
+            .collect()
+    }
+
+    // the text covered by the ranges, joined by the delimiter, or a space
+    pub fn select(&self, line: &[u8], ranges: &[Range]) -> Vec<u8> {
+        let sep: &[u8] = match *self {
+            Delimiter::Whitespace => b" ",
+            Delimiter::Exact(ref delim) => delim,
+        };
+
+        let parts: Vec<&[u8]> = self
+            .spans(line, ranges)
+            .into_iter()
+            .map(|(start, end)| &line[start..end])
+            .collect();
+
+        parts.join(sep)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Delimiter, Range};
+
+    fn select(delimiter: &str, line: &str, ranges: &str) -> String {
+        let ranges = Range::parse_list(ranges).unwrap();
+        let selected = Delimiter::parse(delimiter).select(line.as_bytes(), &ranges);
+
+        String::from_utf8(selected).unwrap()
+    }
+
+    #[test]
+    fn ranges_count_from_one_and_from_the_end() {
+        assert_eq!(select("", "a b c", "1"), "a");
+        assert_eq!(select("", "a b c", "-1"), "c");
+        assert_eq!(select("", "a b c", "2.."), "b c");
+        assert_eq!(select("", "a b c", "..2"), "a b");
+        assert_eq!(select("", "a b c", "-2..-1"), "b c");
+    }
+
+    #[test]
+    fn an_open_range_covers_the_whole_line() {
+        assert_eq!(select("", "a  b c", ".."), "a  b c");
+    }
+
+    #[test]
+    fn fields_past_the_end_are_empty() {
+        assert_eq!(select("", "a b c", "5"), "");
+        assert_eq!(select("", "a b c", "-5"), "");
+        assert_eq!(select("", "a b c", "3..5"), "c");
+        assert_eq!(select("", "a b c", "3..2"), "");
+    }
+
+    #[test]
+    fn field_zero_and_junk_are_rejected() {
+        assert!(Range::parse("0").is_none());
+        assert!(Range::parse("0..2").is_none());
+        assert!(Range::parse("x").is_none());
+        assert!(Range::parse("").is_none());
+        assert!(Range::parse_list("1,x").is_err());
+    }
+
+    #[test]
+    fn several_ranges_are_joined_by_the_delimiter() {
+        assert_eq!(select("", "a b c", "1,3"), "a c");
+        assert_eq!(select(",", "a,b,c", "3, 1"), "c,a");
+    }
+
+    #[test]
+    fn whitespace_runs_at_either_end_are_dropped() {
+        assert_eq!(select("", "  a \t b  ", "1"), "a");
+        assert_eq!(select("", "  a \t b  ", "-1"), "b");
+        assert_eq!(select("", "  a \t b  ", "1.."), "a \t b");
+    }
+
+    #[test]
+    fn a_blank_line_has_no_fields() {
+        assert_eq!(select("", "", "1"), "");
+        assert_eq!(select("", "   ", ".."), "");
+    }
+
+    #[test]
+    fn an_exact_delimiter_allows_empty_fields() {
+        assert_eq!(select("::", "a::::b", "2"), "");
+        assert_eq!(select("::", "a::::b", "3"), "b");
+        assert_eq!(select("::", "a::::b", "1..2"), "a::");
+        assert_eq!(select("::", "::a", "1"), "");
+        assert_eq!(select(",", "", "1"), "");
+    }
+
+    #[test]
+    fn an_escaped_tab_is_a_tab() {
+        assert_eq!(select("\\t", "a b\tc", "1"), "a b");
+        assert_eq!(select("\\t", "a b\tc", "2"), "c");
+    }
+
+    #[test]
+    fn multi_byte_delimiters_split_on_whole_chars() {
+        assert_eq!(select("│", "a│é│c", "2"), "é");
+        assert_eq!(select("│", "a│é│c", "-1"), "c");
+    }
+}
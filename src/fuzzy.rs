@@ -0,0 +1,195 @@
+const SCORE_MATCH: i64 = 16;
+const BONUS_BOUNDARY: i64 = 8;
+const BONUS_CONSECUTIVE: i64 = 6;
+const PENALTY_GAP: i64 = 1;
+
+pub struct Pattern {
+    chars: Vec<char>,
+    ignore_case: bool,
+}
+
+pub struct Match {
+    pub score: i64,
+    pub positions: Vec<usize>,
+}
+
+fn fold(c: char) -> char {
+    if c.is_ascii() {
+        c.to_ascii_lowercase()
+    } else {
+        c.to_lowercase().next().unwrap_or(c)
+    }
+}
+
+fn is_boundary(prev: char, cur: char) -> bool {
+    !prev.is_alphanumeric() || (prev.is_lowercase() && cur.is_uppercase())
+}
+
+impl Pattern {
+    // smart case: the query is only case sensitive if it contains an
+    // uppercase character
+    pub fn new(query: &str) -> Pattern {
+        let ignore_case = !query.chars().any(|c| c.is_uppercase());
+
+        let chars = if ignore_case {
+            query.chars().map(fold).collect()
+        } else {
+            query.chars().collect()
+        };
+
+        Pattern { chars, ignore_case }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.chars.is_empty()
+    }
+
+    fn eq(&self, pc: char, c: char) -> bool {
+        if self.ignore_case {
+            pc == fold(c)
+        } else {
+            pc == c
+        }
+    }
+
+    // positions are char indices into line, not byte offsets
+    pub fn matches(&self, line: &str) -> Option<Match> {
+        if self.is_empty() {
+            return Some(Match {
+                score: 0,
+                positions: vec![],
+            });
+        }
+
+        let chars: Vec<char> = line.chars().collect();
+
+        // find the first position at which the whole pattern has been seen
+        let mut pi = 0;
+        let mut end = None;
+        for (i, &c) in chars.iter().enumerate() {
+            if self.eq(self.chars[pi], c) {
+                pi += 1;
+
+                if pi == self.chars.len() {
+                    end = Some(i);
+                    break;
+                }
+            }
+        }
+        let end = end?;
+
+        // walk back from there to find the tightest window
+        let mut pi = self.chars.len() - 1;
+        let mut start = 0;
+        for i in (0..=end).rev() {
+            if self.eq(self.chars[pi], chars[i]) {
+                if pi == 0 {
+                    start = i;
+                    break;
+                }
+
+                pi -= 1;
+            }
+        }
+
+        let mut positions = Vec::with_capacity(self.chars.len());
+        let mut pi = 0;
+        for (i, &c) in chars.iter().enumerate().take(end + 1).skip(start) {
+            if pi < self.chars.len() && self.eq(self.chars[pi], c) {
+                positions.push(i);
+                pi += 1;
+            }
+        }
+
+        Some(Match {
+            score: score(&chars, &positions),
+            positions,
+        })
+    }
+}
+
+fn score(chars: &[char], positions: &[usize]) -> i64 {
+    let mut score = 0;
+    let mut last: Option<usize> = None;
+
+    for &p in positions {
+        score += SCORE_MATCH;
+
+        if p == 0 || is_boundary(chars[p - 1], chars[p]) {
+            score += BONUS_BOUNDARY;
+        }
+
+        if let Some(l) = last {
+            if p == l + 1 {
+                score += BONUS_CONSECUTIVE;
+            } else {
+                score -= PENALTY_GAP * (p - l - 1) as i64;
+            }
+        }
+
+        last = Some(p);
+    }
+
+    score
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Pattern;
+
+    fn positions(query: &str, line: &str) -> Option<Vec<usize>> {
+        Pattern::new(query).matches(line).map(|m| m.positions)
+    }
+
+    fn score(query: &str, line: &str) -> i64 {
+        Pattern::new(query).matches(line).unwrap().score
+    }
+
+    #[test]
+    fn positions_are_those_of_the_matched_chars() {
+        assert_eq!(positions("abc", "a_b_c"), Some(vec![0, 2, 4]));
+        assert_eq!(positions("abc", "acb"), None);
+    }
+
+    #[test]
+    fn positions_count_chars_rather_than_bytes() {
+        assert_eq!(positions("a", "日本a"), Some(vec![2]));
+        assert_eq!(positions("éb", "xéb"), Some(vec![1, 2]));
+    }
+
+    #[test]
+    fn the_tightest_window_is_matched() {
+        assert_eq!(positions("ab", "a xab"), Some(vec![3, 4]));
+    }
+
+    #[test]
+    fn an_empty_query_matches_everything() {
+        assert_eq!(positions("", "anything"), Some(vec![]));
+        assert_eq!(score("", "anything"), 0);
+    }
+
+    #[test]
+    fn a_lowercase_query_ignores_case() {
+        assert_eq!(positions("abc", "ABC"), Some(vec![0, 1, 2]));
+        assert_eq!(positions("é", "É"), Some(vec![0]));
+    }
+
+    #[test]
+    fn an_uppercase_char_makes_the_query_case_sensitive() {
+        assert_eq!(positions("Abc", "abc"), None);
+        assert_eq!(positions("Abc", "xAbc"), Some(vec![1, 2, 3]));
+    }
+
+    #[test]
+    fn consecutive_chars_rank_above_gaps() {
+        assert!(score("ab", "ab") > score("ab", "axb"));
+        assert!(score("ab", "xab") > score("ab", "xaxxb"));
+    }
+
+    #[test]
+    fn word_boundaries_rank_above_the_middle_of_words() {
+        assert!(score("fb", "foo_bar") > score("fb", "foobar"));
+        assert!(score("fb", "fooBar") > score("fb", "foobar"));
+        assert!(score("b", "b") > score("b", "ab"));
+    }
+}
@@ -0,0 +1,122 @@
+use event::Waker;
+
+use std::ffi::OsString;
+use std::io::{self, BufRead, BufReader, Read};
+use std::process::{Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+struct Pending {
+    lines: Vec<Vec<u8>>,
+    done: bool,
+    closed: bool,
+}
+
+// input is read on its own thread so the list can be used while the
+// producer is still running
+pub struct Input {
+    shared: Arc<Mutex<Pending>>,
+    pub reloaded: bool,
+    // the byte records end with, a newline unless told otherwise
+    pub delimiter: u8,
+}
+
+// lines are kept as the bytes they were read as, minus the delimiter, so
+// that they can be handed back unchanged even if they are not UTF-8
+fn read<R: Read>(shared: &Arc<Mutex<Pending>>, source: R, delimiter: u8, waker: Waker) {
+    for line in BufReader::new(source).split(delimiter) {
+        let mut line = match line {
+            Ok(line) => line,
+            Err(_) => break,
+        };
+
+        if delimiter == b'\n' && line.last() == Some(&b'\r') {
+            line.pop();
+        }
+
+        let mut pending = shared.lock().unwrap();
+
+        if pending.closed {
+            return;
+        }
+
+        // only the first line of a batch needs to wake the ui up, the rest
+        // are picked up along with it
+        if pending.lines.is_empty() {
+            waker.wake();
+        }
+
+        pending.lines.push(line);
+    }
+
+    shared.lock().unwrap().done = true;
+    waker.wake();
+}
+
+impl Input {
+    fn spawn<F>(waker: Waker, delimiter: u8, reloaded: bool, producer: F) -> Input
+    where
+        F: FnOnce(&Arc<Mutex<Pending>>, Waker) + Send + 'static,
+    {
+        let shared = Arc::new(Mutex::new(Pending {
+            lines: vec![],
+            done: false,
+            closed: false,
+        }));
+
+        let reader = shared.clone();
+        thread::spawn(move || producer(&reader, waker));
+
+        Input {
+            shared,
+            reloaded,
+            delimiter,
+        }
+    }
+
+    pub fn stdin(delimiter: u8, waker: Waker) -> Input {
+        Input::spawn(waker, delimiter, false, move |shared, waker| {
+            read(shared, io::stdin(), delimiter, waker)
+        })
+    }
+
+    // reads the output of a shell command, as for reload()
+    pub fn command(cmd: OsString, delimiter: u8, waker: Waker) -> Input {
+        Input::spawn(waker, delimiter, true, move |shared, waker| {
+            let child = Command::new("sh")
+                .arg("-c")
+                .arg(cmd)
+                .stdin(Stdio::null())
+                .stdout(Stdio::piped())
+                .stderr(Stdio::null())
+                .spawn();
+
+            match child {
+                Ok(mut child) => {
+                    read(shared, child.stdout.take().unwrap(), delimiter, waker);
+                    let _ = child.wait();
+                }
+                Err(_) => {
+                    shared.lock().unwrap().done = true;
+                    waker.wake();
+                }
+            }
+        })
+    }
+
+    // returns the lines read since the last call, and whether the end of
+    // the input has been reached
+    pub fn take(&self) -> (Vec<Vec<u8>>, bool) {
+        let mut pending = self.shared.lock().unwrap();
+
+        (std::mem::take(&mut pending.lines), pending.done)
+    }
+}
+
+// whatever the thread reads after this is thrown away, and it stops at the
+// next line
+impl Drop for Input {
+    fn drop(&mut self) {
+        self.shared.lock().unwrap().closed = true;
+    }
+}
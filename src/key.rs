@@ -0,0 +1,318 @@
+use libc;
+
+use std::fs::File;
+use std::io::Read;
+use std::os::unix::io::AsRawFd;
+
+// how long to wait for the rest of an escape sequence before deciding that
+// escape was pressed on its own
+const ESC_TIMEOUT_MS: i32 = 50;
+
+// how long to wait for the terminal to report the cursor position before
+// assuming it never will
+const REPORT_TIMEOUT_MS: i32 = 250;
+
+pub const SHIFT: u8 = 1;
+pub const ALT: u8 = 2;
+pub const CTRL: u8 = 4;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Code {
+    Char(char),
+    Enter,
+    Tab,
+    Backspace,
+    Esc,
+    Up,
+    Down,
+    Left,
+    Right,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+    Insert,
+    Delete,
+    F(u8),
+    Unknown,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct Key {
+    pub code: Code,
+    pub mods: u8,
+}
+
+impl Key {
+    pub fn new(code: Code, mods: u8) -> Key {
+        Key { code, mods }
+    }
+
+    fn plain(code: Code) -> Key {
+        Key::new(code, 0)
+    }
+}
+
+// where keys are decoded from: the terminal, which is given a moment for the
+// rest of a sequence to arrive, or just some bytes
+trait Source {
+    // the next byte, or None if there is none within timeout milliseconds;
+    // without a timeout, waits for as long as it takes
+    fn next(&mut self, timeout: Option<i32>) -> Option<u8>;
+}
+
+impl Source for File {
+    fn next(&mut self, timeout: Option<i32>) -> Option<u8> {
+        if let Some(ms) = timeout {
+            let mut fd = libc::pollfd {
+                fd: self.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            };
+
+            if unsafe { libc::poll(&mut fd, 1, ms) } <= 0 {
+                return None;
+            }
+        }
+
+        let mut buf = [0; 1];
+        self.read_exact(&mut buf).ok().map(|_| buf[0])
+    }
+}
+
+impl Source for &[u8] {
+    fn next(&mut self, _: Option<i32>) -> Option<u8> {
+        let (&first, rest) = self.split_first()?;
+        *self = rest;
+        Some(first)
+    }
+}
+
+fn read_utf8<S: Source>(src: &mut S, first: u8) -> Code {
+    let len = match first {
+        0xc0..=0xdf => 2,
+        0xe0..=0xef => 3,
+        0xf0..=0xf7 => 4,
+        _ => return Code::Unknown,
+    };
+
+    let mut buf = [first, 0, 0, 0];
+
+    for b in buf.iter_mut().take(len).skip(1) {
+        match src.next(Some(ESC_TIMEOUT_MS)) {
+            Some(c) => *b = c,
+            None => return Code::Unknown,
+        }
+    }
+
+    std::str::from_utf8(&buf[..len])
+        .ok()
+        .and_then(|s| s.chars().next())
+        .map_or(Code::Unknown, Code::Char)
+}
+
+fn from_byte<S: Source>(src: &mut S, b: u8) -> Key {
+    match b {
+        13 => Key::plain(Code::Enter),
+        9 => Key::plain(Code::Tab),
+        8 | 127 => Key::plain(Code::Backspace),
+        27 => Key::plain(Code::Esc),
+        0 => Key::new(Code::Char(' '), CTRL),
+        1..=26 => Key::new(Code::Char((b'a' + b - 1) as char), CTRL),
+        28..=31 => Key::new(Code::Char((b'\\' + b - 28) as char), CTRL),
+        0x20..=0x7e => Key::plain(Code::Char(b as char)),
+        _ => Key::plain(read_utf8(src, b)),
+    }
+}
+
+// xterm encodes modifiers as 1 + a bitmask of shift, alt and ctrl, which
+// happens to match our own bits
+fn modifiers(param: Option<u32>) -> u8 {
+    match param {
+        Some(p) if p > 1 => ((p - 1) & 7) as u8,
+        _ => 0,
+    }
+}
+
+fn csi_tilde(n: u32) -> Code {
+    match n {
+        1 | 7 => Code::Home,
+        2 => Code::Insert,
+        3 => Code::Delete,
+        4 | 8 => Code::End,
+        5 => Code::PageUp,
+        6 => Code::PageDown,
+        11..=15 => Code::F((n - 10) as u8),
+        17..=21 => Code::F((n - 11) as u8),
+        23 | 24 => Code::F((n - 12) as u8),
+        _ => Code::Unknown,
+    }
+}
+
+fn final_code(b: u8) -> Code {
+    match b {
+        b'A' => Code::Up,
+        b'B' => Code::Down,
+        b'C' => Code::Right,
+        b'D' => Code::Left,
+        b'H' => Code::Home,
+        b'F' => Code::End,
+        b'P'..=b'S' => Code::F(b - b'P' + 1),
+        _ => Code::Unknown,
+    }
+}
+
+fn read_csi<S: Source>(src: &mut S) -> Key {
+    let mut params = String::new();
+
+    let last = loop {
+        match src.next(Some(ESC_TIMEOUT_MS)) {
+            Some(b @ 0x30..=0x3f) => params.push(b as char),
+            Some(b @ 0x40..=0x7e) => break b,
+            None if params.is_empty() => return Key::new(Code::Char('['), ALT),
+            Some(_) | None => return Key::plain(Code::Unknown),
+        }
+    };
+
+    let params: Vec<Option<u32>> = params.split(';').map(|p| p.parse().ok()).collect();
+    let first = params.first().cloned().unwrap_or(None);
+    let mods = modifiers(params.get(1).cloned().unwrap_or(None));
+
+    match last {
+        b'~' => Key::new(csi_tilde(first.unwrap_or(0)), mods),
+        b'Z' => Key::new(Code::Tab, SHIFT),
+        b => Key::new(final_code(b), mods),
+    }
+}
+
+fn read_escape<S: Source>(src: &mut S) -> Key {
+    match src.next(Some(ESC_TIMEOUT_MS)) {
+        None => Key::plain(Code::Esc),
+        Some(b'[') => read_csi(src),
+        Some(b'O') => match src.next(Some(ESC_TIMEOUT_MS)) {
+            None => Key::new(Code::Char('O'), ALT),
+            Some(b) => Key::plain(final_code(b)),
+        },
+        Some(27) => Key::plain(Code::Esc),
+        Some(b) => {
+            let key = from_byte(src, b);
+            Key::new(key.code, key.mods | ALT)
+        }
+    }
+}
+
+fn decode<S: Source>(src: &mut S) -> Key {
+    match src.next(None) {
+        Some(27) => read_escape(src),
+        Some(b) => from_byte(src, b),
+        None => Key::plain(Code::Unknown),
+    }
+}
+
+// blocks until a whole key has been read
+pub fn read_key(tty: &mut File) -> Key {
+    decode(tty)
+}
+
+fn cursor_report<S: Source>(src: &mut S) -> Option<(usize, usize)> {
+    loop {
+        if src.next(Some(REPORT_TIMEOUT_MS))? != 27 {
+            continue;
+        }
+
+        if src.next(Some(REPORT_TIMEOUT_MS))? != b'[' {
+            continue;
+        }
+
+        let mut params = String::new();
+
+        let last = loop {
+            match src.next(Some(REPORT_TIMEOUT_MS))? {
+                b @ b'0'..=b'9' | b @ b';' => params.push(b as char),
+                b => break b,
+            }
+        };
+
+        if last != b'R' {
+            continue;
+        }
+
+        let mut params = params.split(';').map(|p| p.parse::<usize>().ok());
+
+        if let (Some(Some(row)), Some(Some(col))) = (params.next(), params.next()) {
+            return Some((row.saturating_sub(1), col.saturating_sub(1)));
+        }
+    }
+}
+
+// reads the reply to a cursor position request, ESC [ row ; col R, skipping
+// anything typed in the meantime; the position returned counts from 0
+pub fn read_cursor_report(tty: &mut File) -> Option<(usize, usize)> {
+    cursor_report(tty)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{cursor_report, decode, Code, Key, ALT, CTRL, SHIFT};
+
+    fn key(mut bytes: &[u8]) -> Key {
+        decode(&mut bytes)
+    }
+
+    #[test]
+    fn plain_bytes() {
+        assert_eq!(key(b"a"), Key::new(Code::Char('a'), 0));
+        assert_eq!(key(b"\r"), Key::new(Code::Enter, 0));
+        assert_eq!(key(b"\x7f"), Key::new(Code::Backspace, 0));
+        assert_eq!(key(b"\x01"), Key::new(Code::Char('a'), CTRL));
+    }
+
+    #[test]
+    fn utf8_chars() {
+        assert_eq!(key("é".as_bytes()), Key::new(Code::Char('é'), 0));
+        assert_eq!(key("漢".as_bytes()), Key::new(Code::Char('漢'), 0));
+        assert_eq!(key(b"\xe6\xbc"), Key::new(Code::Unknown, 0));
+    }
+
+    #[test]
+    fn csi_sequences() {
+        assert_eq!(key(b"\x1b[A"), Key::new(Code::Up, 0));
+        assert_eq!(key(b"\x1b[1;5A"), Key::new(Code::Up, CTRL));
+        assert_eq!(key(b"\x1b[5~"), Key::new(Code::PageUp, 0));
+        assert_eq!(key(b"\x1b[3;2~"), Key::new(Code::Delete, SHIFT));
+        assert_eq!(key(b"\x1b[15~"), Key::new(Code::F(5), 0));
+        assert_eq!(key(b"\x1b[Z"), Key::new(Code::Tab, SHIFT));
+    }
+
+    #[test]
+    fn ss3_sequences() {
+        assert_eq!(key(b"\x1bOP"), Key::new(Code::F(1), 0));
+        assert_eq!(key(b"\x1bOH"), Key::new(Code::Home, 0));
+        assert_eq!(key(b"\x1bO"), Key::new(Code::Char('O'), ALT));
+    }
+
+    #[test]
+    fn alt_is_escape_first() {
+        assert_eq!(key(b"\x1bx"), Key::new(Code::Char('x'), ALT));
+        assert_eq!(key(b"\x1b\x01"), Key::new(Code::Char('a'), CTRL | ALT));
+        assert_eq!(key(b"\x1b["), Key::new(Code::Char('['), ALT));
+    }
+
+    #[test]
+    fn escape_on_its_own() {
+        assert_eq!(key(b"\x1b"), Key::new(Code::Esc, 0));
+        assert_eq!(key(b"\x1b\x1b"), Key::new(Code::Esc, 0));
+    }
+
+    #[test]
+    fn cursor_reports_count_from_zero() {
+        assert_eq!(cursor_report(&mut &b"\x1b[24;80R"[..]), Some((23, 79)));
+        assert_eq!(cursor_report(&mut &b"\x1b[1;1R"[..]), Some((0, 0)));
+    }
+
+    #[test]
+    fn cursor_reports_skip_what_was_typed_before() {
+        assert_eq!(cursor_report(&mut &b"jk\x1b[A\x1b[5;3R"[..]), Some((4, 2)));
+        assert_eq!(cursor_report(&mut &b"\x1b[24;80"[..]), None);
+    }
+}
@@ -0,0 +1,65 @@
+// Every line read is kept in one of two arenas rather than in a String of its
+// own: the text shown for it, and the bytes it was read as for the few lines
+// where those differ, such as ones that are not valid UTF-8.  With millions of
+// lines that saves an allocation and a couple of words for each of them.
+
+pub struct Lines {
+    text: String,
+    ends: Vec<usize>,
+    bytes: Vec<u8>,
+    // the line index and the span in bytes, in the order the lines came in
+    originals: Vec<(usize, usize, usize)>,
+}
+
+impl Lines {
+    pub fn new() -> Lines {
+        Lines {
+            text: String::new(),
+            ends: vec![],
+            bytes: vec![],
+            originals: vec![],
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.ends.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ends.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.text.clear();
+        self.ends.clear();
+        self.bytes.clear();
+        self.originals.clear();
+    }
+
+    pub fn push(&mut self, text: &str, raw: &[u8]) {
+        if text.as_bytes() != raw {
+            let start = self.bytes.len();
+            self.bytes.extend_from_slice(raw);
+            self.originals.push((self.len(), start, self.bytes.len()));
+        }
+
+        self.text.push_str(text);
+        self.ends.push(self.text.len());
+    }
+
+    pub fn text(&self, index: usize) -> &str {
+        let start = if index == 0 { 0 } else { self.ends[index - 1] };
+
+        &self.text[start..self.ends[index]]
+    }
+
+    pub fn raw(&self, index: usize) -> &[u8] {
+        match self.originals.binary_search_by_key(&index, |&(i, _, _)| i) {
+            Ok(n) => {
+                let (_, start, end) = self.originals[n];
+                &self.bytes[start..end]
+            }
+            Err(_) => self.text(index).as_bytes(),
+        }
+    }
+}
@@ -0,0 +1,720 @@
+use field::{Delimiter, Range};
+use fuzzy::{Match, Pattern};
+use lines::Lines;
+use preview::{Position, Preview, TAB_WIDTH};
+use search;
+use template::{Item, Template};
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+use std::ffi::OsString;
+
+pub struct ViList {
+    pub lines: Lines,
+    pub delimiter: Delimiter,
+    // the fields shown, and the fields matched against, of each line
+    pub with_nth: Option<Vec<Range>>,
+    pub nth: Option<Vec<Range>>,
+    view: Vec<usize>,
+    scores: Vec<i64>,
+    query: String,
+    // the query the view was last filtered with, which lags behind while
+    // a search is interrupted by more typing
+    searched: String,
+    pub prompt: String,
+    pub typing: bool,
+    pub multi: bool,
+    marked: Vec<bool>,
+    // how many of marked are set, kept up to date rather than counted on
+    // every frame
+    marks: usize,
+    pub selected: usize,
+    pub loading: bool,
+    spinner: usize,
+    pub height: usize,
+    pub width: usize,
+}
+
+const SPINNER: [char; 4] = ['-', '\\', '|', '/'];
+
+const COLOR_NORMAL: &str = "\x1b[0m";
+const COLOR_SELECTED: &str = "\x1b[0m\x1b[1m\x1b[34m";
+const COLOR_MATCH: &str = "\x1b[0m\x1b[32m";
+const COLOR_SELECTED_MATCH: &str = "\x1b[0m\x1b[1m\x1b[32m";
+const COLOR_CURSOR: &str = "\x1b[0m\x1b[7m";
+const COLOR_GUTTER: &str = "\x1b[0m\x1b[1m\x1b[33m";
+const COLOR_BORDER: &str = "\x1b[0m\x1b[2m";
+
+#[derive(Clone, Copy, PartialEq)]
+pub struct Cell {
+    ch: char,
+    color: &'static str,
+    // the columns taken by the grapheme cluster the char starts; the rest of
+    // the chars in one take none, and are joined to the char before them
+    width: usize,
+    joined: bool,
+}
+
+impl Cell {
+    fn new(ch: char, color: &'static str) -> Cell {
+        Cell {
+            ch,
+            color,
+            width: 1,
+            joined: false,
+        }
+    }
+
+    pub fn joined(&self) -> bool {
+        self.joined
+    }
+}
+
+// how a line of input is shown: invalid UTF-8 is replaced and control
+// characters escaped, so that nothing read can move the cursor.  Tabs are
+// kept, so they can still separate fields, and only expanded when drawn.
+fn printable(raw: &[u8], text: &mut String) {
+    let decoded = String::from_utf8_lossy(raw);
+
+    // the usual case, worth not going char by char for with millions of lines
+    if !decoded.chars().any(|c| c.is_control() && c != '\t') {
+        text.push_str(&decoded);
+        return;
+    }
+
+    for c in decoded.chars() {
+        match c {
+            '\t' => text.push(c),
+            '\x00'..='\x1f' => {
+                text.push('^');
+                text.push((c as u8 + b'@') as char);
+            }
+            '\x7f' => text.push_str("^?"),
+            c if c.is_control() => text.push_str(&format!("\\u{{{:x}}}", c as u32)),
+            c => text.push(c),
+        }
+    }
+}
+
+// a cell for each char of the first limit grapheme clusters of the line, so
+// that match positions, which count chars, still index them
+fn clipped_cells(line: &str, limit: usize, color: &'static str) -> Vec<Cell> {
+    // control chars have been escaped, so in ASCII every char is a cluster
+    if line.is_ascii() {
+        return line
+            .chars()
+            .take(limit)
+            .map(|ch| Cell::new(ch, color))
+            .collect();
+    }
+
+    let mut cells = vec![];
+
+    for cluster in line.graphemes(true).take(limit) {
+        let width = UnicodeWidthStr::width(cluster);
+
+        for (i, ch) in cluster.chars().enumerate() {
+            cells.push(Cell {
+                ch,
+                color,
+                width: if i == 0 { width } else { 0 },
+                joined: i > 0,
+            });
+        }
+    }
+
+    cells
+}
+
+fn cells(line: &str, color: &'static str) -> Vec<Cell> {
+    clipped_cells(line, usize::MAX, color)
+}
+
+fn expand_tabs(cells: Vec<Cell>) -> Vec<Cell> {
+    let mut expanded = vec![];
+    let mut col = 0;
+
+    for cell in cells {
+        if cell.ch == '\t' {
+            let n = TAB_WIDTH - col % TAB_WIDTH;
+            expanded.extend(std::iter::repeat_n(Cell::new(' ', cell.color), n));
+            col += n;
+        } else {
+            col += cell.width;
+            expanded.push(cell);
+        }
+    }
+
+    expanded
+}
+
+pub fn cells_width(cells: &[Cell]) -> usize {
+    cells.iter().map(|c| c.width).sum()
+}
+
+fn pad_cells(cells: &mut Vec<Cell>, width: usize) {
+    for _ in cells_width(cells)..width {
+        cells.push(Cell::new(' ', COLOR_NORMAL));
+    }
+}
+
+// a cluster is colored as a whole, whichever of its chars matched
+fn highlight(cells: &mut [Cell], positions: &[usize], color: &'static str) {
+    for &p in positions {
+        if p >= cells.len() {
+            continue;
+        }
+
+        let mut start = p;
+        while start > 0 && cells[start].joined {
+            start -= 1;
+        }
+
+        cells[start].color = color;
+
+        for cell in cells[start + 1..].iter_mut().take_while(|c| c.joined) {
+            cell.color = color;
+        }
+    }
+}
+
+// writes the cells, then clears whatever was left of the line after them
+pub fn write_cells(buf: &mut String, line: &[Cell]) {
+    let mut color = "";
+
+    for cell in line {
+        if cell.color != color {
+            color = cell.color;
+            buf.push_str(color);
+        }
+
+        buf.push(cell.ch);
+    }
+
+    buf.push_str("\x1b[0m\x1b[K");
+}
+
+impl ViList {
+    pub fn new(multi: bool) -> ViList {
+        ViList {
+            height: 0,
+            lines: Lines::new(),
+            delimiter: Delimiter::Whitespace,
+            with_nth: None,
+            nth: None,
+            view: vec![],
+            scores: vec![],
+            query: String::new(),
+            searched: String::new(),
+            prompt: "/".to_string(),
+            typing: false,
+            multi,
+            marked: vec![],
+            marks: 0,
+            selected: 0,
+            loading: true,
+            spinner: 0,
+            width: 0,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.lines.clear();
+        self.view.clear();
+        self.scores.clear();
+        self.marked.clear();
+        self.marks = 0;
+        self.selected = 0;
+        self.loading = true;
+        self.searched = self.query.clone();
+    }
+
+    // adds newly read lines, keeping the cursor on the line it was on
+    pub fn append(&mut self, lines: Vec<Vec<u8>>) {
+        let pattern = Pattern::new(&self.query);
+        let current = self.view.get(self.selected).cloned();
+        let first = self.lines.len();
+        let mut text = String::new();
+
+        for line in lines {
+            text.clear();
+
+            match self.with_nth {
+                Some(ref fields) => printable(&self.delimiter.select(&line, fields), &mut text),
+                None => printable(&line, &mut text),
+            }
+
+            self.lines.push(&text, &line);
+        }
+
+        self.marked.resize(self.lines.len(), false);
+        self.scores.resize(self.lines.len(), 0);
+
+        for i in first..self.lines.len() {
+            if let Some(m) = self.matches(&pattern, i) {
+                self.scores[i] = m.score;
+                self.view.push(i);
+            }
+        }
+
+        if !pattern.is_empty() {
+            self.sort_view();
+
+            if let Some(current) = current {
+                self.selected = self.view.iter().position(|&i| i == current).unwrap_or(0);
+            }
+        }
+
+        self.spinner = (self.spinner + 1) % SPINNER.len();
+    }
+
+    // the pattern is matched against the fields picked by --nth, if given,
+    // but the positions are always those in the whole line as shown
+    fn matches(&self, pattern: &Pattern, index: usize) -> Option<Match> {
+        let text = self.lines.text(index);
+
+        let nth = match self.nth {
+            Some(ref nth) => nth,
+            None => return pattern.matches(text),
+        };
+
+        let mut key = String::new();
+        let mut origin = vec![];
+
+        for (start, end) in self.delimiter.spans(text.as_bytes(), nth) {
+            if !key.is_empty() {
+                key.push(' ');
+                origin.push(text[..start].chars().count());
+            }
+
+            let first = text[..start].chars().count();
+
+            key.push_str(&text[start..end]);
+            origin.extend(first..first + text[start..end].chars().count());
+        }
+
+        pattern.matches(&key).map(|m| Match {
+            score: m.score,
+            positions: m.positions.iter().map(|&p| origin[p]).collect(),
+        })
+    }
+
+    fn sort_view(&mut self) {
+        let scores = &self.scores;
+
+        self.view
+            .sort_by(|&a, &b| scores[b].cmp(&scores[a]).then(a.cmp(&b)));
+    }
+
+    pub fn len(&self) -> usize {
+        self.view.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.view.is_empty()
+    }
+
+    pub fn is_stale(&self) -> bool {
+        self.query != self.searched
+    }
+
+    // finishes a search that was interrupted, for when the view has to match
+    // the query; one that already does is left alone, cursor and all
+    pub fn catch_up(&mut self) {
+        if self.is_stale() {
+            self.search(|| false);
+        }
+    }
+
+    // brings the view up to date with the query, unless interrupted() says
+    // to give up first, in which case the view is left as it was and false
+    // is returned
+    pub fn search<F: Fn() -> bool>(&mut self, interrupted: F) -> bool {
+        let pattern = Pattern::new(&self.query);
+
+        let hits = if pattern.is_empty() {
+            (0..self.lines.len()).map(|i| (i, 0)).collect()
+        } else {
+            let all: Vec<usize>;
+
+            // if the query has only been appended to, the view already has
+            // every line that can still match
+            let candidates = if self.query.starts_with(&self.searched) {
+                &self.view
+            } else {
+                all = (0..self.lines.len()).collect();
+                &all
+            };
+
+            let this = &*self;
+
+            match search::run(
+                candidates,
+                |i| this.matches(&pattern, i).map(|m| m.score),
+                interrupted,
+            ) {
+                Some(hits) => hits,
+                None => return false,
+            }
+        };
+
+        self.view.clear();
+
+        for (i, score) in hits {
+            self.scores[i] = score;
+            self.view.push(i);
+        }
+
+        self.selected = 0;
+        self.searched = self.query.clone();
+
+        true
+    }
+
+    pub fn push_query(&mut self, c: char) {
+        self.query.push(c);
+    }
+
+    pub fn pop_query(&mut self) {
+        self.query.pop();
+    }
+
+    pub fn clear_query(&mut self) {
+        self.query.clear();
+    }
+
+    fn set_mark(&mut self, index: usize, mark: bool) {
+        if self.marked[index] != mark {
+            self.marked[index] = mark;
+
+            if mark {
+                self.marks += 1;
+            } else {
+                self.marks -= 1;
+            }
+        }
+    }
+
+    pub fn toggle_mark(&mut self) {
+        if let Some(&i) = self.view.get(self.selected) {
+            let mark = !self.marked[i];
+            self.set_mark(i, mark);
+        }
+    }
+
+    pub fn mark_all(&mut self) {
+        for n in 0..self.view.len() {
+            let i = self.view[n];
+            self.set_mark(i, true);
+        }
+    }
+
+    pub fn unmark_all(&mut self) {
+        for m in self.marked.iter_mut() {
+            *m = false;
+        }
+
+        self.marks = 0;
+    }
+
+    pub fn invert_marks(&mut self) {
+        for n in 0..self.view.len() {
+            let i = self.view[n];
+            let mark = !self.marked[i];
+            self.set_mark(i, mark);
+        }
+    }
+
+    pub fn can_accept(&self) -> bool {
+        !self.is_empty() || self.marks > 0
+    }
+
+    pub fn up(&mut self) {
+        if self.is_empty() {
+            return;
+        }
+
+        self.selected = if self.selected > 0 {
+            self.selected - 1
+        } else {
+            self.len() - 1
+        };
+    }
+
+    pub fn down(&mut self) {
+        if self.is_empty() {
+            return;
+        }
+
+        self.selected = if self.selected < self.len() - 1 {
+            self.selected + 1
+        } else {
+            0
+        };
+    }
+
+    pub fn page_up(&mut self) {
+        self.selected = self.selected.saturating_sub(self.height.max(1));
+    }
+
+    pub fn page_down(&mut self) {
+        self.selected = (self.selected + self.height.max(1)).min(self.len().saturating_sub(1));
+    }
+
+    fn row(&self, n: usize, pattern: &Pattern) -> Vec<Cell> {
+        let line = self.lines.text(self.view[n]);
+
+        let (color, match_color) = if n == self.selected {
+            (COLOR_SELECTED, COLOR_SELECTED_MATCH)
+        } else {
+            (COLOR_NORMAL, COLOR_MATCH)
+        };
+
+        // however long the line, no more clusters than the row is wide can
+        // fit
+        let mut row = clipped_cells(line, self.width, color);
+
+        if let Some(m) = self.matches(pattern, self.view[n]) {
+            highlight(&mut row, &m.positions, match_color);
+        }
+
+        let mut row = expand_tabs(row);
+
+        if self.multi {
+            let marker = if self.marked[self.view[n]] { '*' } else { ' ' };
+
+            row.insert(0, Cell::new(' ', color));
+            row.insert(0, Cell::new(marker, COLOR_GUTTER));
+        }
+
+        trim_cells(row, self.width)
+    }
+
+    fn start_point(&self) -> (usize, usize) {
+        let len = self.len();
+
+        if len <= self.height {
+            return (0, len);
+        }
+
+        let buffer = self.height / 2;
+
+        let end = if self.selected + buffer >= len {
+            len
+        } else if self.selected + buffer >= self.height {
+            self.selected + buffer + 1
+        } else {
+            self.height
+        };
+
+        (end - self.height, end)
+    }
+
+    fn pct_str(&self) -> String {
+        let pct = if self.is_empty() {
+            0
+        } else {
+            ((self.selected + 1) * 100) / self.len()
+        };
+
+        let status = format!(
+            "{:3}/{:3}, {:3}%",
+            if self.is_empty() {
+                0
+            } else {
+                self.selected + 1
+            },
+            self.len(),
+            pct
+        );
+
+        let status = if self.len() == self.lines.len() {
+            status
+        } else {
+            format!("{} ({})", status, self.lines.len())
+        };
+
+        let status = match self.marks {
+            0 => status,
+            n => format!("{} +{}", status, n),
+        };
+
+        if self.loading {
+            format!("{} {}", status, SPINNER[self.spinner])
+        } else {
+            status
+        }
+    }
+
+    fn prompt(&self) -> Vec<Cell> {
+        if !self.typing && self.query.is_empty() {
+            return vec![];
+        }
+
+        let mut prompt = cells(&format!("{}{}", self.prompt, self.query), COLOR_NORMAL);
+
+        if self.typing {
+            prompt.push(Cell::new(' ', COLOR_CURSOR));
+        }
+
+        trim_cells(prompt, self.width)
+    }
+
+    // the rows of the interface, from the top; only the part of the list
+    // that is in view is looked at
+    pub fn frame(&self, preview: Option<&Preview>) -> Vec<Vec<Cell>> {
+        let mut frame = vec![];
+        let (start, end) = self.start_point();
+
+        let pattern = Pattern::new(&self.query);
+
+        let side = match preview {
+            Some(p) if p.position == Position::Right => Some(p),
+            _ => None,
+        };
+        let side_lines = side.map(|p| p.lines()).unwrap_or_default();
+
+        for row in 0..self.height {
+            let mut line = if start + row < end {
+                self.row(start + row, &pattern)
+            } else {
+                vec![]
+            };
+
+            if let Some(p) = side {
+                pad_cells(&mut line, self.width);
+                line.extend(cells("\u{2502} ", COLOR_BORDER));
+
+                if let Some(text) = side_lines.get(row) {
+                    line.extend(trim_cells(cells(text, COLOR_NORMAL), p.width));
+                }
+            }
+
+            frame.push(line);
+        }
+
+        frame.push(trim_cells(cells(&self.pct_str(), COLOR_NORMAL), self.width));
+        frame.push(self.prompt());
+
+        if let Some(p) = preview.filter(|p| p.position == Position::Bottom) {
+            let border: String = std::iter::repeat_n('\u{2500}', p.width).collect();
+            frame.push(trim_cells(cells(&border, COLOR_BORDER), p.width));
+
+            let lines = p.lines();
+
+            for row in 0..p.height {
+                match lines.get(row) {
+                    Some(text) => frame.push(trim_cells(cells(text, COLOR_NORMAL), p.width)),
+                    None => frame.push(vec![]),
+                }
+            }
+        }
+
+        frame
+    }
+
+    pub fn item(&self, index: usize) -> Item<'_> {
+        Item {
+            index,
+            line: self.lines.raw(index),
+        }
+    }
+
+    pub fn current(&self) -> Option<Item<'_>> {
+        self.view.get(self.selected).map(|&i| self.item(i))
+    }
+
+    // expands the placeholders in a command for execute() and friends, or
+    // gives up if there is nothing to fill them in with
+    pub fn shell_command(&self, cmd: &str) -> Option<OsString> {
+        let template = Template::parse(cmd, &self.delimiter);
+
+        if !template.has_placeholder() {
+            return Some(OsString::from(cmd));
+        }
+
+        self.current()
+            .map(|current| template.command(&current, &self.selections()))
+    }
+
+    pub fn selections(&self) -> Vec<Item<'_>> {
+        if self.marks > 0 {
+            (0..self.lines.len())
+                .filter(|&i| self.marked[i])
+                .map(|i| self.item(i))
+                .collect()
+        } else {
+            self.current().into_iter().collect()
+        }
+    }
+}
+
+fn trim_cells(mut cells: Vec<Cell>, tgt: usize) -> Vec<Cell> {
+    let mut w = 0;
+
+    for (i, cell) in cells.iter().enumerate() {
+        // a cluster that does not fit goes along with the chars joined to it
+        if w + cell.width > tgt {
+            cells.truncate(i);
+            break;
+        };
+
+        w += cell.width;
+    }
+
+    cells
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ViList;
+
+    fn list(lines: &[&str]) -> ViList {
+        let mut list = ViList::new(false);
+        list.height = 10;
+        list.width = 40;
+        list.append(lines.iter().map(|l| l.as_bytes().to_vec()).collect());
+        list.loading = false;
+        list
+    }
+
+    fn current(list: &ViList) -> &[u8] {
+        list.current().unwrap().line
+    }
+
+    #[test]
+    fn catching_up_keeps_the_cursor() {
+        let mut list = list(&["foo", "bar", "baz"]);
+
+        list.down();
+        list.catch_up();
+
+        assert_eq!(current(&list), b"bar");
+    }
+
+    #[test]
+    fn catching_up_keeps_the_cursor_on_a_filtered_view() {
+        let mut list = list(&["foo", "bar", "baz"]);
+
+        list.push_query('b');
+        list.catch_up();
+        list.down();
+        list.catch_up();
+
+        assert_eq!(current(&list), b"baz");
+    }
+
+    #[test]
+    fn catching_up_finishes_an_interrupted_search() {
+        let mut list = list(&["foo", "bar", "baz"]);
+
+        list.push_query('z');
+        assert!(list.is_stale());
+
+        list.catch_up();
+
+        assert!(!list.is_stale());
+        assert_eq!(list.len(), 1);
+        assert_eq!(current(&list), b"baz");
+    }
+}
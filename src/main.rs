@@ -1,15 +1,42 @@
 extern crate clap;
-extern crate termion;
+extern crate libc;
 extern crate termios as term;
+extern crate unicode_segmentation;
 extern crate unicode_width;
 
+mod bind;
+mod error;
+mod event;
+mod field;
+mod fuzzy;
+mod input;
+mod key;
+mod lines;
+mod list;
+mod preview;
+mod search;
+mod template;
+
+use bind::{Action, Bindings, Lookup};
 use clap::{App, AppSettings, Arg, ArgMatches};
-use unicode_width::UnicodeWidthChar;
-
-use std::fs::File;
-use std::io::{self, BufRead, Read, Stdin, Write};
-use std::os::unix::io::AsRawFd;
-use std::process::{exit, Command};
+use error::Error;
+use event::{Event, Events};
+use field::{Delimiter, Range};
+use input::Input;
+use list::{cells_width, write_cells, Cell, ViList};
+use preview::{Position, Preview};
+use template::{Item, Template};
+
+use std::env;
+use std::ffi::OsString;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::os::unix::process::ExitStatusExt;
+use std::panic;
+use std::path::PathBuf;
+use std::process::{exit, Command, Stdio};
+use std::sync::Mutex;
 
 struct TermDim {
     height: usize,
@@ -17,296 +44,935 @@ struct TermDim {
 }
 
 impl TermDim {
-    fn new() -> TermDim {
-        let (width, height) = termion::terminal_size().unwrap();
+    fn new(fd: i32) -> TermDim {
+        let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+
+        unsafe {
+            libc::ioctl(fd, libc::TIOCGWINSZ, &mut size);
+        }
 
         TermDim {
-            width: width as usize,
-            height: height as usize,
+            width: size.ws_col as usize,
+            height: size.ws_row as usize,
         }
     }
 
-    fn civis(&self) {
-        print!("\x1b[?25l");
+    fn civis(&self, out: &mut dyn Write) {
+        write!(out, "\x1b[?25l").unwrap();
     }
 
-    fn cnorm(&self) {
-        print!("\x1b[?25h");
+    fn cnorm(&self, out: &mut dyn Write) {
+        write!(out, "\x1b[?25h").unwrap();
+    }
+}
+
+// how many lines of the terminal to take up, for --height
+#[derive(Clone, Copy)]
+enum Height {
+    Lines(usize),
+    Percent(usize),
+}
+
+// the list, the status line and the prompt need at least this much room
+const MIN_HEIGHT: usize = 3;
+
+impl Height {
+    fn parse(s: &str) -> Result<Height, String> {
+        let height = match s.strip_suffix('%') {
+            Some(pct) => pct.parse().ok().filter(|&p| p <= 100).map(Height::Percent),
+            None => s.parse().ok().map(Height::Lines),
+        };
+
+        height.ok_or_else(|| format!("invalid height '{}', expected N or N%", s))
     }
 
-    fn clear(&self) {
-        for _ in 0..(self.height + 2) {
-            println!("\x1b[K");
+    fn lines(&self, dim: &TermDim) -> usize {
+        let lines = match *self {
+            Height::Lines(n) => n,
+            Height::Percent(p) => dim.height * p / 100,
+        };
+
+        lines.max(MIN_HEIGHT).min(dim.height)
+    }
+}
+
+// splits the area the interface is drawn in between the list, which is
+// followed by the status line and the prompt, and the preview
+fn layout(area: &TermDim, list: &mut ViList, preview: Option<&mut Preview>) {
+    let rows = area.height.saturating_sub(2);
+
+    match preview {
+        None => {
+            list.height = rows.min(list.lines.len());
+            list.width = area.width;
         }
-        print!("\x1b[{}A", self.height + 2);
+        Some(p) => match p.position {
+            Position::Right => {
+                list.height = rows;
+                list.width = area.width / 2;
+                p.height = list.height;
+                p.width = area.width.saturating_sub(list.width + 2);
+            }
+            Position::Bottom => {
+                list.height = (rows / 2).min(list.lines.len());
+                list.width = area.width;
+                p.height = rows.saturating_sub(list.height + 1);
+                p.width = area.width;
+            }
+        },
     }
 }
 
-struct ViList {
-    list: Vec<String>,
-    len: usize,
-    selected: usize,
-    height: usize,
-    width: usize,
+fn uncook_tty(fd: i32, cooked: &term::Termios) {
+    let mut termios = *cooked;
+    term::cfmakeraw(&mut termios);
+    term::tcsetattr(fd, term::TCSANOW, &termios).unwrap();
 }
 
-fn write_line<'t>(stdout: &mut io::StdoutLock, color: &'t str, line: &'t str) {
-    stdout
-        .write_fmt(format_args!(
-            "{}{}\x1b[0m\x1b[K\x1b[1B\x1b[{}D",
-            color,
-            line,
-            line.len()
-        ))
-        .unwrap();
+enum Outcome {
+    Accept,
+    Abort,
+    NoInput,
+    NoMatch,
+    Terminated(i32),
 }
 
-impl ViList {
-    fn build(stdin: Stdin, dim: &TermDim) -> ViList {
-        let list: Vec<String> = stdin.lock().lines().map(|l| l.unwrap()).collect();
-        let len = list.len();
+fn run_action(list: &mut ViList, action: Action) -> Option<Outcome> {
+    match action {
+        Action::Up => list.up(),
+        Action::Down => list.down(),
+        Action::First => list.selected = 0,
+        Action::Last => list.selected = list.len().saturating_sub(1),
+        Action::Middle => list.selected = list.len() / 2,
+        Action::PageUp => list.page_up(),
+        Action::PageDown => list.page_down(),
+        Action::Accept => {
+            // what was typed before accepting is what is being accepted,
+            // even if the list has not caught up with it yet
+            list.catch_up();
+
+            return Some(if list.can_accept() {
+                Outcome::Accept
+            } else {
+                Outcome::NoMatch
+            });
+        }
+        Action::Abort => return Some(Outcome::Abort),
+        Action::ToggleMark => {
+            if list.multi {
+                list.toggle_mark();
+                list.down();
+            }
+        }
+        Action::MarkAll => {
+            if list.multi {
+                list.mark_all();
+            }
+        }
+        Action::UnmarkAll => {
+            if list.multi {
+                list.unmark_all();
+            }
+        }
+        Action::InvertMarks => {
+            if list.multi {
+                list.invert_marks();
+            }
+        }
+        Action::Search => list.typing = true,
+        Action::EndSearch => list.typing = false,
+        Action::BackwardDeleteChar => list.pop_query(),
+        Action::ClearQuery => list.clear_query(),
+        Action::ChangePrompt(prompt) => list.prompt = prompt,
+        Action::Ignore => {}
+        Action::Execute(_) | Action::Reload(_) | Action::Suspend => {}
+    }
 
-        let height = if dim.height / 2 > len {
-            len
-        } else {
-            dim.height / 2
-        };
+    None
+}
+
+// everything needed to talk to the terminal the list is drawn on
+struct Screen {
+    tty: File,
+    out: BufWriter<Box<dyn Write>>,
+    events: Events,
+    win: TermDim,
+    height: Height,
+    // drawn on the alternate screen, leaving the scrollback alone
+    fullscreen: bool,
+    // the row the interface starts on, and where the cursor was before it
+    // was drawn, both counting from 0
+    top: usize,
+    origin: (usize, usize),
+    // what was drawn last, row by row
+    last: Vec<Vec<Cell>>,
+    cooked: term::Termios,
+}
+
+impl Screen {
+    fn new(
+        tty: File,
+        out: Box<dyn Write>,
+        height: Height,
+        fullscreen: bool,
+    ) -> Result<Screen, Error> {
+        let fd = tty.as_raw_fd();
+
+        Ok(Screen {
+            win: TermDim::new(fd),
+            events: Events::new(fd),
+            out: BufWriter::new(out),
+            height: if fullscreen {
+                Height::Percent(100)
+            } else {
+                height
+            },
+            fullscreen,
+            top: 0,
+            origin: (0, 0),
+            last: vec![],
+            cooked: term::Termios::from_fd(fd).map_err(Error::Terminal)?,
+            tty,
+        })
+    }
 
-        ViList {
-            height,
-            len,
-            list,
-            selected: 0,
-            width: dim.width,
+    // the part of the terminal the interface is drawn in
+    fn area(&self) -> TermDim {
+        TermDim {
+            height: self.height.lines(&self.win),
+            width: self.win.width,
         }
     }
 
-    fn trim_list(&self) -> Vec<String> {
-        self.list
-            .iter()
-            .map(|l| trim_string(l.to_string(), self.width))
-            .collect::<Vec<String>>()
+    fn cursor(&mut self) -> Option<(usize, usize)> {
+        write!(self.out, "\x1b[6n").unwrap();
+        self.out.flush().unwrap();
+
+        key::read_cursor_report(&mut self.tty)
     }
 
-    fn start_point(&self) -> (usize, usize) {
-        let end = if self.len > self.height {
-            let buffer = self.height / 2;
+    // scrolls the terminal up, if there are not enough lines left below the
+    // top of the interface, by just as many as are missing
+    fn reserve(&mut self) {
+        let bottom = self.win.height;
+        let missing = (self.top + self.area().height).saturating_sub(bottom);
 
-            if self.selected + buffer >= self.len {
-                self.len
-            } else if self.selected + buffer > self.height {
-                self.selected + 1 + buffer
-            } else {
-                self.height + 1
-            }
-        } else {
-            self.len
-        };
+        if missing > 0 {
+            write!(self.out, "\x1b[{};1H", bottom).unwrap();
+            self.out.write_all(&vec![b'\n'; missing]).unwrap();
+
+            self.top -= missing;
+            self.origin.0 = self.origin.0.saturating_sub(missing);
+        }
+    }
+
+    fn erase(&mut self) {
+        for row in self.top..self.top + self.area().height {
+            write!(self.out, "\x1b[{};1H\x1b[K", row + 1).unwrap();
+        }
+    }
 
-        let start = if end < (self.height + 1) {
-            0
+    // puts the terminal in raw mode and reserves room for the interface,
+    // starting on the line the cursor is on, or the next one if that is not
+    // empty, as after a prompt without a newline
+    fn start(&mut self) {
+        *RAW.lock().unwrap() = Some((self.tty.as_raw_fd(), self.cooked, self.fullscreen));
+        uncook_tty(self.tty.as_raw_fd(), &self.cooked);
+
+        if self.fullscreen {
+            write!(self.out, "\x1b[?1049h").unwrap();
+            self.top = 0;
         } else {
-            end - (self.height + 1)
-        };
+            // without an answer, the bottom line is the safe guess
+            let (row, col) = self
+                .cursor()
+                .unwrap_or((self.win.height.saturating_sub(1), 0));
+
+            self.origin = (row, col);
+            self.top = if col > 0 { row + 1 } else { row };
+            self.reserve();
+        }
 
-        (start, end)
+        self.erase();
+        self.last.clear();
+        self.win.civis(&mut self.out);
+        self.out.flush().unwrap();
     }
 
-    fn pct_str(&self) -> String {
-        format!(
-            "{:3}/{:3}, {:3}%",
-            self.selected + 1,
-            self.len,
-            ((self.selected + 1) * 100) / self.len
-        )
+    fn resize(&mut self) {
+        self.win = TermDim::new(self.tty.as_raw_fd());
+        self.top = self.top.min(self.win.height.saturating_sub(1));
+
+        // the old frame may be wider or taller than the new one, and a
+        // taller one may need more room
+        self.redraw();
+        self.reserve();
     }
 
-    fn display(&self, stdout: &mut io::StdoutLock) {
-        let list = self.trim_list();
+    // blanks everything from the top of the interface down, for when the
+    // last frame can no longer be trusted to be what is on the screen
+    fn redraw(&mut self) {
+        write!(self.out, "\x1b[{};1H\x1b[J", self.top + 1).unwrap();
+        self.last.clear();
+    }
 
-        let (start, end) = self.start_point();
+    // only what changed since the last frame is written, from the first cell
+    // that differs in each row, and all of it at once inside a synchronized
+    // update so that terminals supporting them never show half a frame.  The
+    // cursor is left at the top of the interface between frames.
+    fn draw(&mut self, list: &ViList, preview: Option<&Preview>) {
+        let frame = list.frame(preview);
+        let mut buf = String::from("\x1b[?2026h");
 
-        let mut drew = start;
+        for row in 0..frame.len().max(self.last.len()) {
+            let new = frame.get(row).map_or(&[][..], |l| &l[..]);
+            let old = self.last.get(row).map_or(&[][..], |l| &l[..]);
 
-        for line in list[start..end].iter() {
-            let color = if drew == self.selected {
-                "\x1b[1m\x1b[34m"
-            } else {
-                "\x1b[0m"
-            };
+            if new == old {
+                continue;
+            }
 
-            write_line(stdout, color, line);
+            let mut same = new.iter().zip(old).take_while(|(a, b)| a == b).count();
 
-            drew += 1;
-        }
+            // a cluster is redrawn whole if any of it changed, as the
+            // terminal cannot be pointed at the middle of one
+            while same > 0
+                && [new.get(same), old.get(same)]
+                    .iter()
+                    .any(|c| c.is_some_and(Cell::joined))
+            {
+                same -= 1;
+            }
 
-        write_line(stdout, "0", &self.pct_str());
+            let col = cells_width(&new[..same]);
 
-        stdout
-            .write_fmt(format_args!("\x1b[{}A", (end - start) + 1))
-            .unwrap();
+            buf.push_str(&format!("\x1b[{};{}H", self.top + row + 1, col + 1));
+            write_cells(&mut buf, &new[same..]);
+        }
 
-        stdout.flush().unwrap();
-    }
+        buf.push_str(&format!("\x1b[{};1H\x1b[?2026l", self.top + 1));
 
-    fn selected(&self) -> String {
-        self.list[self.selected].to_string()
+        self.out.write_all(buf.as_bytes()).unwrap();
+        self.out.flush().unwrap();
+        self.last = frame;
     }
-}
 
-fn trim_string(string: String, tgt: usize) -> String {
-    let mut w = 0;
+    // erases the interface and leaves the terminal, and the cursor, the way
+    // they were found; does nothing if that has already been done
+    fn stop(&mut self) {
+        if RAW.lock().unwrap().take().is_none() {
+            return;
+        }
 
-    let mut result = "".to_string();
+        term::tcsetattr(self.tty.as_raw_fd(), term::TCSANOW, &self.cooked).unwrap();
 
-    for c in string.chars() {
-        let cw = UnicodeWidthChar::width(c).unwrap_or(1);
+        if self.fullscreen {
+            write!(self.out, "\x1b[?1049l").unwrap();
+        } else {
+            self.erase();
 
-        if w + cw > tgt {
-            break;
-        };
+            let (row, col) = self.origin;
+            write!(self.out, "\x1b[{};{}H", row + 1, col + 1).unwrap();
+        }
 
-        w += cw;
-        result.push(c);
+        self.win.cnorm(&mut self.out);
+        self.out.flush().unwrap();
     }
 
-    result
-}
+    // raw mode turns off the signal keys, so ctrl-z is handled like any
+    // other binding: put the terminal back, stop the way the shell expects
+    // and take the terminal over again once continued
+    fn suspend(&mut self) {
+        self.stop();
 
-fn uncook_tty(fd: i32) -> term::Termios {
-    let mut termios = term::Termios::from_fd(fd).unwrap();
-    let old_termios = termios;
-    term::cfmakeraw(&mut termios);
-    term::tcsetattr(fd, term::TCSANOW, &termios).unwrap();
+        unsafe {
+            libc::raise(libc::SIGTSTP);
+        }
 
-    old_termios
+        // the terminal may have been resized while we were stopped
+        self.win = TermDim::new(self.tty.as_raw_fd());
+        self.start();
+    }
+
+    fn stdio(&self) -> Stdio {
+        Stdio::from(self.tty.try_clone().unwrap())
+    }
 }
 
-fn select_loop(tty: &mut File, list: &mut ViList) -> bool {
-    let stdout = io::stdout();
-    let mut writer = stdout.lock();
-    let mut buf = [0; 1];
+// whatever way the list is left, the terminal must not stay raw
+impl Drop for Screen {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
 
-    loop {
-        list.display(&mut writer);
+// the settings to put back while the terminal is raw, kept where the panic
+// hook can get at them
+static RAW: Mutex<Option<(RawFd, term::Termios, bool)>> = Mutex::new(None);
+
+// a panic message printed in raw mode comes out mangled, and would then be
+// cleared away along with the list as the stack unwinds, so the terminal is
+// restored before the message is printed
+fn restore_on_panic() {
+    let default = panic::take_hook();
 
-        tty.read_exact(&mut buf[..]).unwrap();
+    panic::set_hook(Box::new(move |info| {
+        let raw = RAW.try_lock().ok().and_then(|mut raw| raw.take());
 
-        match buf[0] {
-            b'q' => {
-                return false;
+        if let Some((fd, cooked, fullscreen)) = raw {
+            let _ = term::tcsetattr(fd, term::TCSANOW, &cooked);
+
+            let reset: &[u8] = if fullscreen {
+                b"\x1b[?1049l\x1b[?25h"
+            } else {
+                b"\r\x1b[J\x1b[?25h"
+            };
+            unsafe {
+                libc::write(fd, reset.as_ptr() as *const libc::c_void, reset.len());
             }
-            b'k' | b'A' | b'h' | b'C' => {
-                list.selected = if list.selected > 0 {
-                    list.selected - 1
-                } else {
-                    list.len - 1
-                };
+        }
+
+        default(info);
+    }));
+}
+
+// runs a command on the terminal without leaving the selector; the list is
+// redrawn from scratch once it exits
+fn execute(screen: &mut Screen, list: &ViList, cmd: &str) {
+    if let Some(cmd) = list.shell_command(cmd) {
+        screen.stop();
+
+        let child = Command::new("sh")
+            .arg("-c")
+            .arg(cmd)
+            .stdin(screen.stdio())
+            .stdout(screen.stdio())
+            .stderr(screen.stdio())
+            .spawn();
+
+        // the command gets the signals as usual, having been started
+        // before they were ignored
+        if let Ok(mut child) = child {
+            let _ = event::ignoring_interrupts(|| child.wait());
+        }
+
+        screen.start();
+    }
+}
+
+// runs a chain of actions; whatever follows a reload() is put off until the
+// new input has been read, so that reload(...)+last means something
+fn run_chain(
+    screen: &mut Screen,
+    input: &mut Input,
+    list: &mut ViList,
+    mut preview: Option<&mut Preview>,
+    chain: Vec<Action>,
+    deferred: &mut Vec<Action>,
+) -> Option<Outcome> {
+    let mut chain = chain.into_iter();
+
+    while let Some(action) = chain.next() {
+        match action {
+            Action::Execute(cmd) => execute(screen, list, &cmd),
+            Action::Suspend => {
+                screen.suspend();
+                layout(&screen.area(), list, preview.as_deref_mut());
             }
-            b'j' | b'B' | b'l' | b'D' => {
-                list.selected = if list.selected < list.len - 1 {
-                    list.selected + 1
-                } else {
-                    0
-                };
+            Action::Reload(cmd) => {
+                if let Some(cmd) = list.shell_command(&cmd) {
+                    *input = Input::command(cmd, input.delimiter, screen.events.waker());
+                    *deferred = chain.collect();
+
+                    list.reset();
+                    layout(&screen.area(), list, preview.as_deref_mut());
+                    screen.redraw();
+
+                    return None;
+                }
             }
-            b'g' => {
-                list.selected = 0;
+            action => {
+                if let Some(outcome) = run_action(list, action) {
+                    return Some(outcome);
+                }
             }
-            b'G' => {
-                list.selected = list.len - 1;
+        }
+    }
+
+    None
+}
+
+fn select_loop(
+    screen: &mut Screen,
+    input: &mut Input,
+    bindings: &mut Bindings,
+    list: &mut ViList,
+    mut preview: Option<&mut Preview>,
+) -> Outcome {
+    let mut deferred = vec![];
+
+    loop {
+        if let Some(ref mut p) = preview {
+            p.update(list.current(), || list.selections());
+        }
+
+        screen.draw(list, preview.as_deref());
+
+        // a search gives way to anything else that needs handling, and is
+        // started over once it has been
+        if list.is_stale() && list.search(|| screen.events.pending()) {
+            continue;
+        }
+
+        match screen.events.wait() {
+            Event::Key => {}
+            Event::Terminate(sig) => return Outcome::Terminated(sig),
+            Event::Resize => {
+                screen.resize();
+                layout(&screen.area(), list, preview.as_deref_mut());
+                continue;
             }
-            b'z' => {
-                list.selected = list.len / 2;
+            Event::Wake => {
+                let (lines, done) = input.take();
+
+                if !lines.is_empty() {
+                    list.append(lines);
+                    layout(&screen.area(), list, preview.as_deref_mut());
+                }
+
+                if done && list.loading {
+                    list.loading = false;
+
+                    if list.lines.is_empty() && !input.reloaded {
+                        return Outcome::NoInput;
+                    }
+
+                    let chain = std::mem::take(&mut deferred);
+
+                    if let Some(outcome) = run_chain(
+                        screen,
+                        input,
+                        list,
+                        preview.as_deref_mut(),
+                        chain,
+                        &mut deferred,
+                    ) {
+                        return outcome;
+                    }
+                }
+
+                continue;
             }
-            13 => {
-                break;
+        }
+
+        let key = key::read_key(&mut screen.tty);
+
+        match bindings.lookup(key, list.typing) {
+            Lookup::Actions(actions) => {
+                if let Some(outcome) = run_chain(
+                    screen,
+                    input,
+                    list,
+                    preview.as_deref_mut(),
+                    actions,
+                    &mut deferred,
+                ) {
+                    return outcome;
+                }
             }
-            _ => {}
+            Lookup::Insert(c) => list.push_query(c),
+            Lookup::Pending | Lookup::None => {}
         }
     }
+}
+
+fn config_path() -> Option<PathBuf> {
+    env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|h| PathBuf::from(h).join(".config")))
+        .map(|dir| dir.join("vsel").join("config"))
+}
+
+fn load_bindings(opts: &ArgMatches) -> Result<Bindings, String> {
+    let mut bindings = Bindings::new();
+
+    let config = opts
+        .value_of("config")
+        .map(PathBuf::from)
+        .or_else(config_path);
+
+    if let Some(path) = config {
+        bindings.load(&path)?;
+    }
+
+    for spec in opts.values_of("bind").into_iter().flatten() {
+        bindings.bind(spec)?;
+    }
 
-    true
+    Ok(bindings)
+}
+
+fn fields(opts: &ArgMatches, name: &str) -> Result<Option<Vec<Range>>, String> {
+    opts.value_of(name).map(Range::parse_list).transpose()
+}
+
+fn record_delimiter(opts: &ArgMatches) -> Result<u8, String> {
+    if opts.is_present("read0") {
+        return Ok(b'\0');
+    }
+
+    match opts.value_of("record-delimiter") {
+        None | Some("\\n") => Ok(b'\n'),
+        Some("\\0") => Ok(b'\0'),
+        Some("\\t") => Ok(b'\t'),
+        Some(d) if d.len() == 1 => Ok(d.as_bytes()[0]),
+        Some(d) => Err(format!(
+            "record delimiter must be a single byte, got '{}'",
+            d
+        )),
+    }
 }
 
 fn parse_options() -> ArgMatches<'static> {
     App::new("Visual SELect")
         .version("0.1.0")
         .author("Stone Tickle")
-        .about("select a line from stdin and execute the specified command")
+        .about("select a line from stdin and execute the specified command, or print it")
         .setting(AppSettings::TrailingVarArg)
-        .arg(Arg::with_name("command").required(true).multiple(true))
+        // clap turns {n} into a line break in help text, except where a
+        // template puts it as is, which {after-help} does
+        .template(
+            "{bin} {version}\n{author}\n{about}\n\nUSAGE:\n    {usage}\n\n{all-args}\n\n{after-help}",
+        )
+        .after_help(
+            "PLACEHOLDERS:
+    {}      the line under the cursor
+    {n}     the index of that line in the input, counting from 0
+    {1}     the first field of the line, {-1} the last
+    {2..}   fields 2 through the last, {..2} and {2..3} work the same way
+    {+...}  any of the above, for every selected line instead of just one
+
+KEYS:
+    Keys are bound with --bind, or in the config file with one key = action
+    per line under a [bind] line.  A key is a single char, a run of them like
+    gg, or one of enter, tab, btab, bspace, esc, space, colon, comma, up, down,
+    left, right, home, end, pgup, pgdn, ins, del or f1 to f12, any of which
+    may follow ctrl-, alt- or shift-.  Actions are chained with +, as in
+    ctrl-r:reload(ls)+first.  While searching, plain chars are typed into
+    the query whatever they are bound to.
+
+ACTIONS:                  default keys                  while searching
+    up                    k, h, up, left, ctrl-p        up, ctrl-p
+    down                  j, l, down, right, ctrl-n     down, ctrl-n
+    first                 g, home
+    last                  G, end
+    middle                z
+    page-up               pgup, ctrl-b                  pgup
+    page-down             pgdn, ctrl-f                  pgdn
+    accept                enter                         enter
+    abort                 q, esc, ctrl-c, ctrl-g        ctrl-c, ctrl-g
+    toggle-mark           tab, space                    tab
+    mark-all              a
+    unmark-all            u
+    invert-marks          i
+    search                /
+    end-search                                          esc
+    backward-delete-char                                bspace
+    clear-query                                         ctrl-u
+    suspend               ctrl-z                        ctrl-z
+    ignore                does nothing, to unbind a key
+    execute(cmd)          runs a shell command on the terminal
+    reload(cmd)           replaces the input with the output of a shell command
+    change-prompt(text)   shows text in front of the query instead of /
+
+EXIT STATUS:
+    0      a selection was made and the command, if any, succeeded
+    1      accepted with nothing matching the query
+    2      bad options, bindings or configuration, or no terminal
+    3      no input
+    126    the command could not be run
+    127    the command was not found
+    128+n  the command, or vsel itself, was killed by signal n
+    130    aborted
+    other  the exit status of the failing command",
+        )
+        .arg(
+            Arg::with_name("command")
+                .multiple(true)
+                .help("command to run on accept, with the placeholders listed below"),
+        )
         .arg(
             Arg::with_name("multi")
                 .short("m")
                 .help("enables multiple selections"),
         )
-        .get_matches()
+        .arg(
+            Arg::with_name("stderr")
+                .long("stderr")
+                .help("draws the interface on stderr instead of /dev/tty"),
+        )
+        .arg(
+            Arg::with_name("height")
+                .long("height")
+                .takes_value(true)
+                .value_name("N|N%")
+                .default_value("50%")
+                .help("how many lines of the terminal to take up"),
+        )
+        .arg(
+            Arg::with_name("fullscreen")
+                .long("fullscreen")
+                .conflicts_with("height")
+                .help("takes up the whole terminal, on the alternate screen"),
+        )
+        .arg(
+            Arg::with_name("preview")
+                .long("preview")
+                .takes_value(true)
+                .value_name("cmd")
+                .help("shows the output of a shell command for the line under the cursor"),
+        )
+        .arg(
+            Arg::with_name("preview-position")
+                .long("preview-position")
+                .takes_value(true)
+                .possible_values(&["right", "bottom"])
+                .default_value("right")
+                .help("where to show the preview"),
+        )
+        .arg(
+            Arg::with_name("bind")
+                .long("bind")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .value_name("key:action")
+                .help("binds keys to actions, e.g. gg:first or ctrl-r:reload(ls)+first"),
+        )
+        .arg(
+            Arg::with_name("config")
+                .long("config")
+                .takes_value(true)
+                .value_name("file")
+                .help("reads key bindings from file instead of ~/.config/vsel/config"),
+        )
+        .arg(
+            Arg::with_name("read0")
+                .long("read0")
+                .help("reads input records separated by NUL instead of newline"),
+        )
+        .arg(
+            Arg::with_name("print0")
+                .long("print0")
+                .help("prints selections terminated by NUL instead of newline"),
+        )
+        .arg(
+            Arg::with_name("record-delimiter")
+                .long("record-delimiter")
+                .takes_value(true)
+                .value_name("char")
+                .conflicts_with("read0")
+                .help("reads input records separated by char, which may also be \\0 or \\t"),
+        )
+        .arg(
+            Arg::with_name("delimiter")
+                .short("d")
+                .long("delimiter")
+                .takes_value(true)
+                .value_name("str")
+                .help("splits lines into fields at str, which may be \\t, instead of whitespace"),
+        )
+        .arg(
+            Arg::with_name("with-nth")
+                .long("with-nth")
+                .takes_value(true)
+                .value_name("fields")
+                .help("shows only these fields of each line, e.g. 2.. or 1,3"),
+        )
+        .arg(
+            Arg::with_name("nth")
+                .long("nth")
+                .takes_value(true)
+                .value_name("fields")
+                .help("matches the query against only these fields of each line as shown"),
+        )
+        .arg(
+            Arg::with_name("each")
+                .short("e")
+                .requires_all(&["multi", "command"])
+                .help("executes the command once for each selection"),
+        )
+        .get_matches_safe()
+        .unwrap_or_else(|e| {
+            // clap would exit with 1, which means no match here
+            if e.use_stderr() {
+                eprintln!("{}", e.message);
+                exit(error::EXIT_ERROR);
+            }
+
+            e.exit()
+        })
 }
 
 struct Cmd {
     path: String,
-    args: Vec<String>,
+    args: Vec<Template>,
 }
 
 impl Cmd {
-    fn parse(parts: clap::Values) -> Cmd {
+    fn parse(parts: clap::Values, delimiter: &Delimiter) -> Cmd {
         let parts: Vec<String> = parts.map(|v| v.to_string()).collect();
 
         let (head, args) = parts.split_at(1);
         let path = head.first().unwrap().to_string();
-        let args = args.iter().map(|v| v.to_string()).collect();
+        let args = args.iter().map(|v| Template::parse(v, delimiter)).collect();
 
         Cmd { path, args }
     }
 
-    fn exec(&self, value: &str) -> Option<i32> {
-        Command::new(&self.path)
-            .args(&self.args)
-            .arg(value)
+    fn args(&self, current: &Item, selected: &[Item]) -> Vec<OsString> {
+        template::arguments(&self.args, current, selected)
+    }
+
+    // stdin has been used up by the list, so the command reads from the
+    // terminal instead
+    fn exec(&self, current: &Item, selected: &[Item], stdin: Stdio) -> Result<(), Error> {
+        let status = Command::new(&self.path)
+            .args(self.args(current, selected))
+            .stdin(stdin)
             .status()
-            .unwrap()
-            .code()
+            .map_err(|e| match e.kind() {
+                io::ErrorKind::NotFound => Error::NotFound(self.path.clone()),
+                _ => Error::Exec(self.path.clone(), e),
+            })?;
+
+        match status.code() {
+            Some(0) => Ok(()),
+            Some(code) => Err(Error::Failed(code)),
+            None => Err(Error::Killed(status.signal().unwrap_or(0))),
+        }
     }
 }
 
-fn main() {
+fn run() -> Result<(), Error> {
     let opts = parse_options();
-    let cmd = Cmd::parse(opts.values_of("command").unwrap());
+    let delimiter = Delimiter::parse(opts.value_of("delimiter").unwrap_or(""));
+    let cmd = opts
+        .values_of("command")
+        .map(|values| Cmd::parse(values, &delimiter));
+
+    let mut bindings = load_bindings(&opts).map_err(Error::Usage)?;
+    let separator = record_delimiter(&opts).map_err(Error::Usage)?;
+    let with_nth = fields(&opts, "with-nth").map_err(Error::Usage)?;
+    let nth = fields(&opts, "nth").map_err(Error::Usage)?;
+    let height = Height::parse(opts.value_of("height").unwrap()).map_err(Error::Usage)?;
+    let terminator = if opts.is_present("print0") {
+        b'\0'
+    } else {
+        b'\n'
+    };
 
-    let win = TermDim::new();
-    let mut list = ViList::build(io::stdin(), &win);
+    let tty = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/tty")
+        .map_err(Error::Tty)?;
 
-    if list.len == 0 {
-        exit(1);
+    let out: Box<dyn Write> = if opts.is_present("stderr") {
+        Box::new(io::stderr())
+    } else {
+        Box::new(tty.try_clone().map_err(Error::Terminal)?)
     };
 
-    let mut stdin = File::open("/dev/tty").unwrap();
-    win.clear();
-    win.civis();
-    let cooked = uncook_tty(stdin.as_raw_fd());
+    let mut screen = Screen::new(tty, out, height, opts.is_present("fullscreen"))?;
 
-    loop {
-        if select_loop(&mut stdin, &mut list) {
-            match cmd.exec(&list.selected()) {
-                None => exit(1),
-                Some(code) => {
-                    if code != 0 {
-                        exit(code);
-                    }
-                }
-            };
+    let mut list = ViList::new(opts.is_present("multi"));
+
+    list.with_nth = with_nth;
+    list.nth = nth;
+    list.delimiter = delimiter.clone();
+
+    let mut input = Input::stdin(separator, screen.events.waker());
+    screen.events.watch_signals();
+    restore_on_panic();
 
-            if !opts.is_present("multi") {
-                break;
+    let mut preview = opts.value_of("preview").map(|cmd| {
+        let position = match opts.value_of("preview-position") {
+            Some("bottom") => Position::Bottom,
+            _ => Position::Right,
+        };
+
+        Preview::new(cmd, &delimiter, position, screen.events.waker())
+    });
+
+    layout(&screen.area(), &mut list, preview.as_mut());
+
+    screen.start();
+
+    let outcome = select_loop(
+        &mut screen,
+        &mut input,
+        &mut bindings,
+        &mut list,
+        preview.as_mut(),
+    );
+
+    if let Some(ref mut p) = preview {
+        p.stop();
+    }
+
+    screen.stop();
+
+    match outcome {
+        Outcome::Accept => {}
+        Outcome::Abort => return Err(Error::Abort),
+        Outcome::NoInput => return Err(Error::NoInput),
+        Outcome::NoMatch => return Err(Error::NoMatch),
+        Outcome::Terminated(sig) => return Err(Error::Terminated(sig)),
+    }
+
+    let selections = list.selections();
+
+    let cmd = match cmd {
+        Some(cmd) => cmd,
+        None => {
+            let stdout = io::stdout();
+            let mut stdout = stdout.lock();
+
+            for item in selections {
+                stdout
+                    .write_all(item.line)
+                    .and_then(|_| stdout.write_all(&[terminator]))
+                    .map_err(Error::Output)?;
             }
-        } else {
-            break;
+
+            return Ok(());
         }
+    };
+    let current = list.current().unwrap_or(selections[0]);
+
+    let batches: Vec<(&Item, &[Item])> = if opts.is_present("each") {
+        selections
+            .iter()
+            .map(|i| (i, std::slice::from_ref(i)))
+            .collect()
+    } else {
+        vec![(&current, &selections)]
+    };
+
+    for (current, selected) in batches {
+        cmd.exec(current, selected, screen.stdio())?;
     }
 
-    term::tcsetattr(stdin.as_raw_fd(), term::TCSANOW, &cooked).unwrap();
-    win.clear();
-    win.cnorm();
-    print!("\x1b[1A\x1b[K");
+    Ok(())
+}
+
+fn main() {
+    if let Err(e) = run() {
+        if !e.is_silent() {
+            eprintln!("vsel: {}", e);
+        }
+
+        exit(e.code());
+    }
 }
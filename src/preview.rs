@@ -0,0 +1,221 @@
+use libc;
+
+use event::Waker;
+use field::Delimiter;
+use template::{Item, Template};
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+use std::ffi::OsString;
+use std::io::{BufRead, BufReader};
+use std::os::unix::process::CommandExt;
+use std::process::{Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+const MAX_LINES: usize = 500;
+// the columns between tab stops, here and in the list
+pub const TAB_WIDTH: usize = 8;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Position {
+    Right,
+    Bottom,
+}
+
+struct Shared {
+    generation: u64,
+    pid: Option<u32>,
+    lines: Vec<String>,
+}
+
+pub struct Preview {
+    template: Template,
+    pub position: Position,
+    pub width: usize,
+    pub height: usize,
+    shared: Arc<Mutex<Shared>>,
+    waker: Waker,
+    showing: Option<usize>,
+}
+
+// tabs are expanded and escape sequences and other control characters
+// dropped, so every char left occupies the columns it claims
+fn sanitize(line: &str) -> String {
+    let mut kept = String::new();
+    let mut chars = line.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\t' => kept.push(c),
+            '\x1b' => {
+                if let Some('[') = chars.next() {
+                    for c in chars.by_ref() {
+                        if ('\x40'..='\x7e').contains(&c) {
+                            break;
+                        }
+                    }
+                }
+            }
+            c if c.is_control() => {}
+            c => kept.push(c),
+        }
+    }
+
+    // the width of a grapheme cluster is not always that of its chars added
+    // up, so the column is counted a cluster at a time
+    let mut result = String::new();
+    let mut col = 0;
+
+    for cluster in kept.graphemes(true) {
+        if cluster == "\t" {
+            let n = TAB_WIDTH - col % TAB_WIDTH;
+            result.extend(std::iter::repeat_n(' ', n));
+            col += n;
+        } else {
+            result.push_str(cluster);
+            col += UnicodeWidthStr::width(cluster);
+        }
+    }
+
+    result
+}
+
+fn run(shared: &Arc<Mutex<Shared>>, waker: Waker, generation: u64, cmd: OsString) {
+    let child = Command::new("sh")
+        .arg("-c")
+        .arg(cmd)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .process_group(0)
+        .spawn();
+
+    let mut child = match child {
+        Ok(child) => child,
+        Err(_) => return,
+    };
+
+    {
+        let mut shared = shared.lock().unwrap();
+
+        if shared.generation != generation {
+            kill(child.id());
+        } else {
+            shared.pid = Some(child.id());
+        }
+    }
+
+    let stdout = BufReader::new(child.stdout.take().unwrap());
+
+    for line in stdout.split(b'\n').take(MAX_LINES) {
+        let line = match line {
+            Ok(line) => line,
+            Err(_) => break,
+        };
+
+        let mut shared = shared.lock().unwrap();
+
+        if shared.generation != generation {
+            break;
+        }
+
+        shared.lines.push(sanitize(&String::from_utf8_lossy(&line)));
+        waker.wake();
+    }
+
+    {
+        let mut shared = shared.lock().unwrap();
+
+        if shared.generation == generation {
+            shared.pid = None;
+        }
+    }
+
+    let _ = child.wait();
+}
+
+fn kill(pid: u32) {
+    unsafe {
+        libc::kill(-(pid as libc::pid_t), libc::SIGTERM);
+    }
+}
+
+impl Preview {
+    pub fn new(cmd: &str, delimiter: &Delimiter, position: Position, waker: Waker) -> Preview {
+        Preview {
+            template: Template::parse(cmd, delimiter),
+            position,
+            width: 0,
+            height: 0,
+            shared: Arc::new(Mutex::new(Shared {
+                generation: 0,
+                pid: None,
+                lines: vec![],
+            })),
+            waker,
+            showing: None,
+        }
+    }
+
+    // restarts the preview command if the line under the cursor changed,
+    // killing the one still running for the previous line; the selections
+    // are only asked for if the command needs them, as with many lines
+    // marked that takes a while
+    pub fn update<'a, F>(&mut self, current: Option<Item>, selections: F)
+    where
+        F: FnOnce() -> Vec<Item<'a>>,
+    {
+        let index = current.map(|i| i.index);
+
+        if index == self.showing {
+            return;
+        }
+
+        self.showing = index;
+
+        let generation = {
+            let mut shared = self.shared.lock().unwrap();
+
+            if let Some(pid) = shared.pid.take() {
+                kill(pid);
+            }
+
+            shared.generation += 1;
+            shared.lines.clear();
+            shared.generation
+        };
+
+        if let Some(current) = current {
+            let selected = if self.template.uses_selections() {
+                selections()
+            } else {
+                vec![]
+            };
+
+            let cmd = self.template.command(&current, &selected);
+            let shared = self.shared.clone();
+            let waker = self.waker;
+
+            thread::spawn(move || run(&shared, waker, generation, cmd));
+        }
+    }
+
+    pub fn lines(&self) -> Vec<String> {
+        let shared = self.shared.lock().unwrap();
+
+        shared.lines.iter().take(self.height).cloned().collect()
+    }
+
+    pub fn stop(&mut self) {
+        self.showing = None;
+
+        let mut shared = self.shared.lock().unwrap();
+
+        if let Some(pid) = shared.pid.take() {
+            kill(pid);
+        }
+
+        shared.generation += 1;
+    }
+}
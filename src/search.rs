@@ -0,0 +1,127 @@
+// Matching a query against a large input is split into chunks of lines that
+// are handed out to one thread per cpu.  Each chunk is sorted by score on the
+// thread that matched it, and the sorted chunks merged once all are done.
+//
+// Between chunks the threads check whether the search is still wanted, so one
+// that has been overtaken by another keypress stops within a few milliseconds
+// rather than after going through every line.
+
+use std::cmp::{Ordering as Order, Reverse};
+use std::collections::BinaryHeap;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::thread;
+
+const CHUNK: usize = 16 * 1024;
+
+// the line index and its score
+type Hit = (usize, i64);
+
+// best score first, and lines that score the same in the order they came in
+fn order(a: &Hit, b: &Hit) -> Order {
+    b.1.cmp(&a.1).then(a.0.cmp(&b.0))
+}
+
+fn work<F, I>(
+    candidates: &[usize],
+    matches: &F,
+    next: &AtomicUsize,
+    cancelled: &AtomicBool,
+    interrupted: I,
+) -> Vec<Vec<Hit>>
+where
+    F: Fn(usize) -> Option<i64>,
+    I: Fn() -> bool,
+{
+    let mut done = vec![];
+
+    loop {
+        if cancelled.load(Ordering::Relaxed) {
+            break;
+        }
+
+        if interrupted() {
+            cancelled.store(true, Ordering::Relaxed);
+            break;
+        }
+
+        let start = next.fetch_add(1, Ordering::Relaxed) * CHUNK;
+
+        if start >= candidates.len() {
+            break;
+        }
+
+        let end = candidates.len().min(start + CHUNK);
+
+        let mut hits: Vec<Hit> = candidates[start..end]
+            .iter()
+            .filter_map(|&i| matches(i).map(|score| (i, score)))
+            .collect();
+
+        hits.sort_by(order);
+        done.push(hits);
+    }
+
+    done
+}
+
+fn merge(chunks: Vec<Vec<Hit>>) -> Vec<Hit> {
+    let mut merged = Vec::with_capacity(chunks.iter().map(Vec::len).sum());
+    let mut heap = BinaryHeap::new();
+
+    for (n, chunk) in chunks.iter().enumerate() {
+        if let Some(&(i, score)) = chunk.first() {
+            heap.push(Reverse((Reverse(score), i, n, 0)));
+        }
+    }
+
+    while let Some(Reverse((Reverse(score), i, n, pos))) = heap.pop() {
+        merged.push((i, score));
+
+        if let Some(&(i, score)) = chunks[n].get(pos + 1) {
+            heap.push(Reverse((Reverse(score), i, n, pos + 1)));
+        }
+    }
+
+    merged
+}
+
+// the candidates that match, best first, or None if interrupted() said to give
+// up first; it is only ever called on the calling thread, between chunks
+pub fn run<F, I>(candidates: &[usize], matches: F, interrupted: I) -> Option<Vec<Hit>>
+where
+    F: Fn(usize) -> Option<i64> + Sync,
+    I: Fn() -> bool,
+{
+    let chunks = candidates.len().div_ceil(CHUNK);
+    let threads = thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1)
+        .min(chunks);
+
+    let next = AtomicUsize::new(0);
+    let cancelled = AtomicBool::new(false);
+
+    let mut done = thread::scope(|s| {
+        let workers: Vec<_> = (1..threads)
+            .map(|_| s.spawn(|| work(candidates, &matches, &next, &cancelled, || false)))
+            .collect();
+
+        let mut done = work(candidates, &matches, &next, &cancelled, interrupted);
+
+        for worker in workers {
+            done.extend(worker.join().unwrap());
+        }
+
+        done
+    });
+
+    if cancelled.load(Ordering::Relaxed) {
+        return None;
+    }
+
+    Some(match done.len() {
+        0 => vec![],
+        1 => done.pop().unwrap(),
+        _ => merge(done),
+    })
+}
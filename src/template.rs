@@ -0,0 +1,318 @@
+// Placeholders understood in command arguments:
+//
+//   {}       the line under the cursor
+//   {n}      the index of that line in the input, counting from 0
+//   {1}      the first field of the line, {-1} the last
+//   {2..}    fields 2 through the last, {..2} and {2..3} work the same way
+//   {+...}   any of the above, for every selected line instead of just one
+//
+// Fields are split as described in field.rs, by --delimiter if given.
+//
+// The command run on accept is given its arguments directly, with no shell
+// in between, so values are substituted as they are.  An argument consisting
+// of nothing but a placeholder expands to one argument per value for {+...},
+// one embedded in a larger argument to the values separated by spaces.
+//
+// Commands that do go through sh -c, those of execute(), reload() and
+// --preview, get every value single quoted instead, so that whatever a line
+// contains it stays a single word to the shell.
+//
+// Lines are substituted exactly as they were read, which need not be valid
+// UTF-8, so everything here works on bytes.
+
+use field::{Delimiter, Range};
+
+use std::ffi::OsString;
+use std::os::unix::ffi::OsStringExt;
+
+#[derive(Clone, Copy)]
+pub struct Item<'a> {
+    pub index: usize,
+    pub line: &'a [u8],
+}
+
+#[derive(Clone, Copy)]
+enum Kind {
+    Line,
+    Index,
+    Fields(Range),
+}
+
+#[derive(Clone, Copy)]
+struct Placeholder {
+    plus: bool,
+    kind: Kind,
+}
+
+enum Token {
+    Lit(String),
+    Place(Placeholder),
+}
+
+pub struct Template {
+    tokens: Vec<Token>,
+    delimiter: Delimiter,
+}
+
+fn parse_placeholder(inner: &str) -> Option<Placeholder> {
+    let (plus, inner) = if let Some(rest) = inner.strip_prefix('+') {
+        (true, rest)
+    } else {
+        (false, inner)
+    };
+
+    let kind = if inner.is_empty() {
+        Kind::Line
+    } else if inner == "n" {
+        Kind::Index
+    } else {
+        Kind::Fields(Range::parse(inner)?)
+    };
+
+    Some(Placeholder { plus, kind })
+}
+
+fn quote(s: &[u8]) -> Vec<u8> {
+    let mut quoted = vec![b'\''];
+
+    for &b in s {
+        match b {
+            b'\'' => quoted.extend_from_slice(b"'\\''"),
+            b => quoted.push(b),
+        }
+    }
+
+    quoted.push(b'\'');
+    quoted
+}
+
+impl Placeholder {
+    fn value(&self, item: &Item, delimiter: &Delimiter) -> Vec<u8> {
+        match self.kind {
+            Kind::Line => item.line.to_vec(),
+            Kind::Index => item.index.to_string().into_bytes(),
+            Kind::Fields(range) => delimiter.select(item.line, &[range]),
+        }
+    }
+
+    fn values(&self, current: &Item, selected: &[Item], delimiter: &Delimiter) -> Vec<Vec<u8>> {
+        if self.plus {
+            selected.iter().map(|i| self.value(i, delimiter)).collect()
+        } else {
+            vec![self.value(current, delimiter)]
+        }
+    }
+}
+
+impl Template {
+    pub fn parse(arg: &str, delimiter: &Delimiter) -> Template {
+        let mut tokens = vec![];
+        let mut lit = String::new();
+        let mut rest = arg;
+
+        while let Some(open) = rest.find('{') {
+            let placeholder = rest[open..].find('}').and_then(|close| {
+                parse_placeholder(&rest[open + 1..open + close]).map(|p| (p, close))
+            });
+
+            match placeholder {
+                Some((p, close)) => {
+                    lit.push_str(&rest[..open]);
+
+                    if !lit.is_empty() {
+                        tokens.push(Token::Lit(lit.clone()));
+                        lit.clear();
+                    }
+
+                    tokens.push(Token::Place(p));
+                    rest = &rest[open + close + 1..];
+                }
+                None => {
+                    lit.push_str(&rest[..=open]);
+                    rest = &rest[open + 1..];
+                }
+            }
+        }
+
+        lit.push_str(rest);
+
+        if !lit.is_empty() || tokens.is_empty() {
+            tokens.push(Token::Lit(lit));
+        }
+
+        Template {
+            tokens,
+            delimiter: delimiter.clone(),
+        }
+    }
+
+    pub fn has_placeholder(&self) -> bool {
+        self.tokens.iter().any(|t| match *t {
+            Token::Place(_) => true,
+            Token::Lit(_) => false,
+        })
+    }
+
+    // whether any of the placeholders is for every selected line
+    pub fn uses_selections(&self) -> bool {
+        self.tokens.iter().any(|t| match *t {
+            Token::Place(p) => p.plus,
+            Token::Lit(_) => false,
+        })
+    }
+
+    // the literal text with the values of the placeholders in between,
+    // each quoted for the shell if asked to be
+    fn substitute(&self, current: &Item, selected: &[Item], quoted: bool) -> Vec<u8> {
+        let mut arg = vec![];
+
+        for token in &self.tokens {
+            match *token {
+                Token::Lit(ref s) => arg.extend_from_slice(s.as_bytes()),
+                Token::Place(ref p) => {
+                    let values: Vec<Vec<u8>> = p
+                        .values(current, selected, &self.delimiter)
+                        .into_iter()
+                        .map(|v| if quoted { quote(&v) } else { v })
+                        .collect();
+
+                    arg.extend(values.join(&b' '));
+                }
+            }
+        }
+
+        arg
+    }
+
+    // the arguments for a command run without a shell
+    pub fn expand(&self, current: &Item, selected: &[Item]) -> Vec<OsString> {
+        if let [Token::Place(ref p)] = self.tokens[..] {
+            return p
+                .values(current, selected, &self.delimiter)
+                .into_iter()
+                .map(OsString::from_vec)
+                .collect();
+        }
+
+        vec![OsString::from_vec(
+            self.substitute(current, selected, false),
+        )]
+    }
+
+    // the whole template as a single string, for sh -c
+    pub fn command(&self, current: &Item, selected: &[Item]) -> OsString {
+        OsString::from_vec(self.substitute(current, selected, true))
+    }
+}
+
+// the arguments of a command run without a shell; if none of them has a
+// placeholder, the selected lines are added to the end as they were read
+pub fn arguments(templates: &[Template], current: &Item, selected: &[Item]) -> Vec<OsString> {
+    let mut args: Vec<OsString> = templates
+        .iter()
+        .flat_map(|t| t.expand(current, selected))
+        .collect();
+
+    if !templates.iter().any(|t| t.has_placeholder()) {
+        args.extend(selected.iter().map(|i| OsString::from_vec(i.line.to_vec())));
+    }
+
+    args
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Item, Template};
+    use field::Delimiter;
+
+    use std::ffi::OsString;
+
+    const LINES: [&str; 3] = ["a b c", "it's", "x y"];
+
+    fn item(index: usize) -> Item<'static> {
+        Item {
+            index,
+            line: LINES[index].as_bytes(),
+        }
+    }
+
+    fn template(arg: &str) -> Template {
+        Template::parse(arg, &Delimiter::Whitespace)
+    }
+
+    fn strings(args: Vec<OsString>) -> Vec<String> {
+        args.into_iter().map(|a| a.into_string().unwrap()).collect()
+    }
+
+    // {} is the first line, and the first and last are selected
+    fn expand(arg: &str) -> Vec<String> {
+        strings(template(arg).expand(&item(0), &[item(0), item(2)]))
+    }
+
+    fn command(arg: &str, current: usize) -> String {
+        let cmd = template(arg).command(&item(current), &[item(0), item(1)]);
+
+        cmd.into_string().unwrap()
+    }
+
+    #[test]
+    fn placeholders_expand_to_the_current_line() {
+        assert_eq!(expand("{}"), ["a b c"]);
+        assert_eq!(expand("{n}"), ["0"]);
+        assert_eq!(expand("{1}"), ["a"]);
+        assert_eq!(expand("{-1}"), ["c"]);
+        assert_eq!(expand("{2..}"), ["b c"]);
+    }
+
+    #[test]
+    fn a_lone_plus_placeholder_is_an_argument_per_selection() {
+        assert_eq!(expand("{+}"), ["a b c", "x y"]);
+        assert_eq!(expand("{+n}"), ["0", "2"]);
+        assert_eq!(expand("{+-1}"), ["c", "y"]);
+    }
+
+    #[test]
+    fn embedded_placeholders_are_joined_by_spaces_and_not_quoted() {
+        assert_eq!(expand("x{}y"), ["xa b cy"]);
+        assert_eq!(expand("x{+}y"), ["xa b c x yy"]);
+        assert_eq!(expand("--file={1}.bak"), ["--file=a.bak"]);
+    }
+
+    #[test]
+    fn commands_for_the_shell_quote_every_value() {
+        assert_eq!(command("echo {}", 1), "echo 'it'\\''s'");
+        assert_eq!(command("{}", 0), "'a b c'");
+        assert_eq!(command("rm {+}", 0), "rm 'a b c' 'it'\\''s'");
+    }
+
+    #[test]
+    fn anything_else_in_braces_stays_literal() {
+        assert_eq!(expand("{foo}"), ["{foo}"]);
+        assert_eq!(expand("{0}"), ["{0}"]);
+        assert_eq!(expand("{"), ["{"]);
+        assert_eq!(expand("}{"), ["}{"]);
+        assert!(!template("{foo}").has_placeholder());
+    }
+
+    #[test]
+    fn a_brace_before_a_placeholder_stays_literal() {
+        assert_eq!(expand("{{}"), ["{a b c"]);
+        assert_eq!(expand("{{}}"), ["{a b c}"]);
+    }
+
+    #[test]
+    fn selections_are_appended_without_a_placeholder() {
+        let templates = [template("-v")];
+        let args = super::arguments(&templates, &item(0), &[item(1), item(2)]);
+
+        assert_eq!(strings(args), ["-v", "it's", "x y"]);
+    }
+
+    #[test]
+    fn selections_are_not_appended_with_a_placeholder() {
+        let templates = [template("-v"), template("{1}")];
+        let args = super::arguments(&templates, &item(0), &[item(1), item(2)]);
+
+        assert_eq!(strings(args), ["-v", "a"]);
+    }
+}